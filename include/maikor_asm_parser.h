@@ -0,0 +1,70 @@
+#ifndef MAIKOR_ASM_PARSER_H
+#define MAIKOR_ASM_PARSER_H
+
+/* Generated with cbindgen, don't edit. Regenerate with `cbindgen --output include/maikor_asm_parser.h` */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/*
+ An error from `maikor_asm_assemble`
+ */
+typedef struct MaikorAsmError {
+  /*
+   Zero based line number, `SIZE_MAX` if the error isn't on a line
+   */
+  size_t line;
+  /*
+   Zero based byte offset of the argument or token the error is about (or the start of the
+   code if it isn't known), `SIZE_MAX` if the error isn't on a line
+   */
+  size_t column;
+  /*
+   Stable error code, e.g. `E0013`
+   */
+  char *code;
+  char *message;
+} MaikorAsmError;
+
+/*
+ Output of `maikor_asm_assemble`, `bytes` is NULL if there are any errors
+ */
+typedef struct MaikorAsmResult {
+  uint8_t *bytes;
+  size_t bytes_len;
+  struct MaikorAsmError *errors;
+  size_t errors_len;
+} MaikorAsmResult;
+
+
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Assembles `len` bytes of UTF-8 source (which doesn't need to be NUL terminated)
+
+ Returns NULL if `source` is NULL and `len` isn't 0, otherwise the result must be freed with
+ `maikor_asm_result_free`
+
+ # Safety
+
+ `source` must point to at least `len` readable bytes
+ */
+struct MaikorAsmResult *maikor_asm_assemble(const uint8_t *source, size_t len);
+
+/*
+ Frees a result from `maikor_asm_assemble`, including the bytes and errors
+
+ # Safety
+
+ `result` must be NULL or from `maikor_asm_assemble` and not already freed
+ */
+void maikor_asm_result_free(struct MaikorAsmResult *result);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MAIKOR_ASM_PARSER_H */
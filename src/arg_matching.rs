@@ -1,8 +1,11 @@
 use crate::arg_patterns::ARG_MATCHES;
+use crate::forms::{op_forms, pattern_to_args};
 use crate::ParseError;
 use crate::ParseError::*;
+use maikor_platform::op_params::{MASK, REGISTER};
 
 #[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Argument {
     Address(u16),
     Register(u8),
@@ -24,6 +27,11 @@ impl Argument {
         }
     }
 
+    /// Register args without pre/post increment/decrement
+    pub fn is_plain_register(&self) -> bool {
+        matches!(self, Argument::Register(reg) | Argument::ExtReg(reg) if reg & MASK == REGISTER)
+    }
+
     pub fn to_offset_bytes(&self) -> Vec<u8> {
         if let Argument::IndirectReg(_, offset_reg, offset_num) = self {
             let mut output = vec![];
@@ -64,20 +72,19 @@ pub fn get_op_code(line_num: usize, op_name: &str, pattern: &str) -> Result<u8,
         if let Some(op_code) = map.get(pattern) {
             Ok(*op_code)
         } else {
-            let options: Vec<&&str> = map.keys().collect();
-            let options_text = format!("{:?}", options);
+            let forms = op_forms(op_name)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|form| form.args)
+                .collect();
             if pattern.is_empty() {
-                Err(MissingArguments(
-                    line_num,
-                    op_name.to_string(),
-                    options_text,
-                ))
+                Err(MissingArguments(line_num, op_name.to_string(), forms))
             } else {
                 Err(InvalidArguments(
                     line_num,
-                    pattern.to_string(),
+                    pattern_to_args(pattern),
                     op_name.to_string(),
-                    options_text,
+                    forms,
                 ))
             }
         }
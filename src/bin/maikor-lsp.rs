@@ -0,0 +1,5 @@
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    maikor_asm_parser::lsp::run()
+}
@@ -0,0 +1,345 @@
+use crate::symbols::Symbols;
+use crate::Line;
+use crate::ParseError::{InvalidDirective, UnterminatedBlock};
+use crate::{BlockKind, DirectiveReason, ParseError};
+use maikor_platform::registers::id;
+
+const IF_PREFIX: &str = ".IF_";
+const ELSE: &str = ".ELSE";
+const IF_END: &str = ".ENDIF";
+const LOOP: &str = ".LOOP_UNTIL";
+const LOOP_END: &str = ".ENDL";
+
+/// Condition name and the jump op used when it's false
+const CONDITIONS: [(&str, &str); 6] = [
+    ("EQ", "JNE"),
+    ("NE", "JE"),
+    ("LT", "JGE"),
+    ("GE", "JL"),
+    ("GT", "JLE"),
+    ("LE", "JG"),
+];
+
+/// Code generated for a control directive (`.if_eq`, `.else`, etc)
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct ControlCode {
+    /// Real ops
+    pub code: Vec<String>,
+    /// Hidden label at the address of the directive
+    pub label_before: Option<String>,
+    /// Hidden label at the address after the directive
+    pub label_after: Option<String>,
+}
+
+enum Block {
+    If {
+        line_num: usize,
+        directive: String,
+        id: usize,
+        has_else: bool,
+    },
+    Loop {
+        line_num: usize,
+        /// Ops run by `.endl`
+        code: Vec<String>,
+    },
+}
+
+/// Returns true if `op` is a control directive
+pub fn is_control_directive(op: &str) -> bool {
+    let op = op.to_ascii_uppercase();
+    op.starts_with(IF_PREFIX) || [ELSE, IF_END, LOOP, LOOP_END].contains(&op.as_str())
+}
+
+/// Reads `.if_<cond>`/`.else`/`.endif` and `.loop_until`/`.endl` blocks, adding the generated
+/// code and hidden labels to `symbols`
+///
+/// ```text
+/// .if_eq al, 0     CMP.B al, 0; JNE else
+///   ...
+/// .else            JMP end; else:
+///   ...
+/// .endif           end:
+///
+/// .loop_until eq   start:
+///   ...
+/// .endl            JNE start
+/// ```
+///
+/// Args after the condition are compared with `CMP` first, otherwise the flags are used as is
+pub fn read_control_blocks(lines: &[Line], symbols: &mut Symbols) -> Result<(), ParseError> {
+    let mut stack: Vec<Block> = vec![];
+    let mut next_id = 0;
+    for line in lines {
+        let (name, args) = match &line.command {
+            Some((op, args)) if is_control_directive(op) => (op, args),
+            _ => continue,
+        };
+        let op = name.to_ascii_uppercase();
+        let invalid = |reason| InvalidDirective(line.num, line.original.trim().to_string(), reason);
+        let mut control = ControlCode::default();
+        if op.starts_with(IF_PREFIX) {
+            let cond = &name[IF_PREFIX.len()..];
+            let id = next_id;
+            next_id += 1;
+            let jump = jump_if_false(cond).ok_or_else(|| invalid(unknown(cond)))?;
+            control.code = compare(args).map_err(invalid)?;
+            control
+                .code
+                .push(format!("{jump} {}", if_label(id, "else")));
+            stack.push(Block::If {
+                line_num: line.num,
+                directive: name.clone(),
+                id,
+                has_else: false,
+            });
+        } else if op == LOOP {
+            let id = next_id;
+            next_id += 1;
+            let cond = args
+                .first()
+                .ok_or_else(|| invalid(DirectiveReason::MissingCondition))?;
+            let jump = jump_if_false(cond).ok_or_else(|| invalid(unknown(cond)))?;
+            let mut code = compare(&args[1..]).map_err(invalid)?;
+            code.push(format!("{jump} {}", loop_label(id)));
+            symbols.add_hidden_label(line.num, &loop_label(id))?;
+            control.label_before = Some(loop_label(id));
+            stack.push(Block::Loop {
+                line_num: line.num,
+                code,
+            });
+        } else {
+            if !args.is_empty() {
+                return Err(invalid(DirectiveReason::UnexpectedArgs));
+            }
+            match (op.as_str(), stack.last_mut()) {
+                (ELSE, Some(Block::If { id, has_else, .. })) => {
+                    if *has_else {
+                        return Err(invalid(DirectiveReason::DuplicateElse));
+                    }
+                    *has_else = true;
+                    control.code = vec![format!("JMP {}", if_label(*id, "end"))];
+                    control.label_after = Some(if_label(*id, "else"));
+                }
+                (IF_END, Some(Block::If { id, has_else, .. })) => {
+                    //without .else the jump from .if_<cond> is to here
+                    let part = if *has_else { "end" } else { "else" };
+                    control.label_before = Some(if_label(*id, part));
+                    stack.pop();
+                }
+                (LOOP_END, Some(Block::Loop { code, .. })) => {
+                    control.code = code.clone();
+                    stack.pop();
+                }
+                (_, Some(Block::If { line_num, .. })) => {
+                    return Err(invalid(DirectiveReason::UnclosedIf(*line_num)));
+                }
+                (_, Some(Block::Loop { line_num, .. })) => {
+                    return Err(invalid(DirectiveReason::UnclosedLoop(*line_num)));
+                }
+                (_, None) => return Err(invalid(DirectiveReason::NoBlockToEnd)),
+            }
+            for label in control.label_before.iter().chain(&control.label_after) {
+                symbols.add_hidden_label(line.num, label)?;
+            }
+        }
+        symbols.add_control_code(line.num, control);
+    }
+    match stack.pop() {
+        Some(Block::If {
+            line_num,
+            directive,
+            ..
+        }) => Err(UnterminatedBlock(line_num, directive, BlockKind::If)),
+        Some(Block::Loop { line_num, .. }) => Err(UnterminatedBlock(
+            line_num,
+            String::from(".loop_until"),
+            BlockKind::Loop,
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Returns the jump op used when the condition is false
+fn jump_if_false(name: &str) -> Option<&'static str> {
+    CONDITIONS
+        .into_iter()
+        .find(|(cond, _)| cond.eq_ignore_ascii_case(name))
+        .map(|(_, jump)| jump)
+}
+
+fn unknown(name: &str) -> DirectiveReason {
+    DirectiveReason::UnknownCondition(name.to_string())
+}
+
+/// Returns a `CMP` op for `args`, if there are any
+///
+/// The size is taken from the first arg, which must be a register
+fn compare(args: &[String]) -> Result<Vec<String>, DirectiveReason> {
+    match args {
+        [] => Ok(vec![]),
+        [first, second] => {
+            let size = match id::from_name(&first.to_ascii_uppercase()) {
+                Ok(reg) if id::size(reg) == 2 => "W",
+                Ok(_) => "B",
+                Err(_) => return Err(DirectiveReason::CompareSize(first.to_string())),
+            };
+            Ok(vec![format!("CMP.{size} {first}, {second}")])
+        }
+        _ => Err(DirectiveReason::CompareArgs),
+    }
+}
+
+fn if_label(id: usize, part: &str) -> String {
+    format!("__if{id}_{part}")
+}
+
+fn loop_label(id: usize) -> String {
+    format!("__loop{id}")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{lint, parse_program, ControlFlowGraph, LintOptions, ParseError};
+    use maikor_platform::ops::*;
+
+    fn bytes(lines: &[&str]) -> Vec<u8> {
+        parse_program(lines).unwrap().bytes
+    }
+
+    #[test]
+    fn test_if() {
+        assert_eq!(
+            bytes(&[".if_eq al, 1", "inc.b al", ".endif", "nop"]),
+            vec![CMP_REG_NUM_BYTE, 1, 1, JNE_ADDR, 0, 8, INC_REG_BYTE, 1, NOP]
+        );
+    }
+
+    #[test]
+    fn test_if_else() {
+        assert_eq!(
+            bytes(&["cmp.w ax, bx", ".if_lt", "nop", ".else", "halt", ".endif"]),
+            vec![
+                CMP_REG_REG_WORD,
+                9,
+                10,
+                JGE_ADDR,
+                0,
+                10,
+                NOP,
+                JMP_ADDR,
+                0,
+                11,
+                HALT
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loop() {
+        assert_eq!(
+            bytes(&["main: nop", ".loop_until ge, bx, 10", "inc.w bx", ".endl"]),
+            vec![
+                NOP,
+                INC_REG_WORD,
+                10,
+                CMP_REG_NUM_WORD,
+                10,
+                0,
+                10,
+                JL_ADDR,
+                0,
+                1
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nesting() {
+        let program = parse_program(&[
+            ".loop_until eq",
+            "  .if_ne al, 0",
+            "    .if_gt",
+            "      nop",
+            "    .endif",
+            "  .else",
+            "    dec.b al",
+            "  .endif",
+            ".endl",
+        ])
+        .unwrap();
+        let labels = &program.labels;
+        assert_eq!(labels["__loop0"], 0);
+        //CMP.B al, 0; JE
+        assert_eq!(labels["__if1_else"], 13);
+        //JLE; NOP
+        assert_eq!(labels["__if2_else"], 10);
+        //JMP; DEC.B al
+        assert_eq!(labels["__if1_end"], 15);
+        assert_eq!(program.bytes.len(), 18);
+        assert_eq!(&program.bytes[15..], &[JNE_ADDR, 0, 0]);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let lines = [
+            "main: .if_eq al, 0",
+            "  nop",
+            ".else",
+            "  halt",
+            ".endif",
+            "  ret",
+        ];
+        let program = parse_program(&lines).unwrap();
+        let graph = ControlFlowGraph::new(&program);
+        let addresses: Vec<u16> = graph.blocks.iter().map(|block| block.address).collect();
+        assert_eq!(addresses, vec![0, 6, 10, 11]);
+        assert!(lint(&lines, &LintOptions::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |lines: &[&str]| parse_program(lines).unwrap_err();
+        assert_eq!(
+            err(&[".if_eq", "nop"]).to_string(),
+            ".if_eq on line 0 was never closed, expected .endif"
+        );
+        assert_eq!(
+            err(&[".loop_until ne", ".if_eq", ".endl"]).to_string(),
+            "Invalid directive .endl on line 2: expected .else or .endif to close if on line 1"
+        );
+        assert_eq!(
+            err(&[".if_eq", ".endif", ".endif"]).to_string(),
+            "Invalid directive .endif on line 2: no block to end"
+        );
+        assert_eq!(
+            err(&[".if_eq", ".else", ".else", ".endif"]).to_string(),
+            "Invalid directive .else on line 2: if block already has .else"
+        );
+        assert_eq!(
+            err(&[".if_zero", ".endif"]).to_string(),
+            "Invalid directive .if_zero on line 0: unknown condition 'zero', expected eq, ne, lt, ge, gt or le"
+        );
+        assert!(matches!(
+            err(&[".if_eq (bx), 1", ".endif"]),
+            ParseError::InvalidDirective(0, _, _)
+        ));
+        assert!(matches!(
+            err(&[".loop_until", ".endl"]),
+            ParseError::InvalidDirective(0, _, _)
+        ));
+        //names used for hidden labels can't be used
+        assert_eq!(
+            err(&[".loop_until eq", ".endl", "__loop0: nop"]).to_string(),
+            "Invalid label __loop0 on line 2: names starting with __ are reserved for control directives"
+        );
+        assert_eq!(
+            err(&["__if0_end: nop", ".if_eq", ".endif"]).to_string(),
+            "Invalid label __if0_end on line 0: names starting with __ are reserved for control directives"
+        );
+        assert_eq!(
+            err(&[".struct __loop0", ".b x", ".endstruct"]).to_string(),
+            "Invalid directive .struct __loop0 on line 0: '__loop0' starts with __ which is reserved for control directives"
+        );
+    }
+}
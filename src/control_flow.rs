@@ -0,0 +1,366 @@
+use crate::arg_matching::Argument;
+use crate::{ParsedLine, Program};
+use maikor_platform::ops::{
+    CALL_ADDR, CALL_REG, EHALT, HALT, JRB_BYTE, JRF_BYTE, MAY_JMP_OPS, MUST_JMP_OPS, RET, RETI,
+};
+use std::ops::Range;
+
+/// How an instruction affects the program counter
+///
+/// Targets are None if they aren't known until runtime (e.g. `JMP ax`)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Flow {
+    /// Continues to the next instruction, also used for directives and empty lines
+    Next,
+    /// Always jumps (`JMP`, `JRF`, `JRB`)
+    Jump(Option<u16>),
+    /// May jump or continue (`JE`, `JNE`, etc)
+    Branch(Option<u16>),
+    /// Jumps and returns to the next instruction
+    Call(Option<u16>),
+    Return,
+    Halt,
+}
+
+impl Flow {
+    /// Returns the flow for a line
+    ///
+    /// Relative jumps (`JRF`/`JRB`) are relative to the address of the jump
+    pub fn of(line: &ParsedLine) -> Flow {
+        //pseudo-ops and control directives (e.g. `.if_eq`) only jump in their last op
+        if let Some(last) = line.expansion.last() {
+            return Flow::of(last);
+        }
+        let is_directive = line
+            .line
+            .command
+            .as_ref()
+            .map(|(op, _)| op.starts_with('.'))
+            .unwrap_or(true);
+        let op_code = match line.bytes.first() {
+            Some(op_code) if !is_directive => *op_code,
+            _ => return Flow::Next,
+        };
+        let target = match line.arguments.first() {
+            Some(Argument::Address(addr)) => Some(*addr),
+            _ => None,
+        };
+        let offset = match line.arguments.first() {
+            Some(Argument::Byte(offset)) => *offset as u16,
+            _ => 0,
+        };
+        match op_code {
+            HALT | EHALT => Flow::Halt,
+            RET | RETI => Flow::Return,
+            CALL_ADDR | CALL_REG => Flow::Call(target),
+            JRF_BYTE => Flow::Jump(Some(line.address.wrapping_add(offset))),
+            JRB_BYTE => Flow::Jump(Some(line.address.wrapping_sub(offset))),
+            _ if MUST_JMP_OPS.contains(&op_code) => Flow::Jump(target),
+            _ if MAY_JMP_OPS.contains(&op_code) => Flow::Branch(target),
+            _ => Flow::Next,
+        }
+    }
+
+    /// True if the next instruction may run after this one
+    pub fn continues(&self) -> bool {
+        matches!(self, Flow::Next | Flow::Branch(_) | Flow::Call(_))
+    }
+
+    pub fn target(&self) -> Option<u16> {
+        match self {
+            Flow::Jump(target) | Flow::Branch(target) | Flow::Call(target) => *target,
+            Flow::Next | Flow::Return | Flow::Halt => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EdgeKind {
+    /// To the next block
+    Next,
+    Jump,
+    /// Conditional jump taken
+    Branch,
+    Call,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Edge {
+    /// Index of the block in `ControlFlowGraph.blocks`
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BasicBlock {
+    /// Indexes of the lines in `Program.lines`
+    pub lines: Range<usize>,
+    pub address: u16,
+    /// Size in bytes
+    pub size: usize,
+    /// Label of the first line, if any
+    pub label: Option<String>,
+    /// Flow of the last instruction
+    pub flow: Flow,
+    pub edges: Vec<Edge>,
+}
+
+impl BasicBlock {
+    /// True if the block ends with a jump/call that has a target not known until runtime or
+    /// that doesn't match the start of a block (e.g. in the middle of an instruction)
+    pub fn has_unresolved_target(&self, graph: &ControlFlowGraph) -> bool {
+        match (self.flow, self.flow.target()) {
+            (Flow::Jump(_) | Flow::Branch(_) | Flow::Call(_), None) => true,
+            (_, Some(target)) => graph.block_at(target).is_none(),
+            _ => false,
+        }
+    }
+}
+
+/// Basic blocks of a program, split at labels, jump targets and after any op that may change
+/// the program counter (jumps, calls, returns and halts)
+///
+/// Block 0 is the entry point
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(program: &Program) -> Self {
+        let lines = &program.lines;
+        let flows: Vec<Flow> = lines.iter().map(Flow::of).collect();
+        let targets: Vec<u16> = flows.iter().filter_map(Flow::target).collect();
+        let mut starts = vec![];
+        for (idx, line) in lines.iter().enumerate() {
+            let previous = idx.checked_sub(1);
+            let after_flow = previous
+                .map(|prev| flows[prev] != Flow::Next)
+                .unwrap_or(true);
+            //only the first line at an address, as empty lines share it with the next line
+            let is_target = targets.contains(&line.address)
+                && previous
+                    .map(|prev| lines[prev].address != line.address)
+                    .unwrap_or(true);
+            if after_flow || line.line.label.is_some() || is_target {
+                starts.push(idx);
+            }
+        }
+        let mut blocks: Vec<BasicBlock> = starts
+            .iter()
+            .enumerate()
+            .map(|(i, start)| {
+                let end = starts.get(i + 1).copied().unwrap_or(lines.len());
+                let flow = lines[*start..end]
+                    .iter()
+                    .zip(&flows[*start..end])
+                    .filter(|(line, _)| !line.bytes.is_empty())
+                    .map(|(_, flow)| *flow)
+                    .next_back()
+                    .unwrap_or(Flow::Next);
+                BasicBlock {
+                    lines: *start..end,
+                    address: lines[*start].address,
+                    size: lines[*start..end].iter().map(|line| line.bytes.len()).sum(),
+                    label: lines[*start].line.label.clone(),
+                    flow,
+                    edges: vec![],
+                }
+            })
+            .collect();
+        let addresses: Vec<u16> = blocks.iter().map(|block| block.address).collect();
+        let count = blocks.len();
+        for (idx, block) in blocks.iter_mut().enumerate() {
+            if let Some(to) = block
+                .flow
+                .target()
+                .and_then(|addr| addresses.iter().position(|start| *start == addr))
+            {
+                let kind = match block.flow {
+                    Flow::Branch(_) => EdgeKind::Branch,
+                    Flow::Call(_) => EdgeKind::Call,
+                    _ => EdgeKind::Jump,
+                };
+                block.edges.push(Edge { to, kind });
+            }
+            if block.flow.continues() && idx + 1 < count {
+                block.edges.push(Edge {
+                    to: idx + 1,
+                    kind: EdgeKind::Next,
+                });
+            }
+        }
+        ControlFlowGraph { blocks }
+    }
+
+    /// Returns the block starting at `address`
+    pub fn block_at(&self, address: u16) -> Option<&BasicBlock> {
+        self.blocks.iter().find(|block| block.address == address)
+    }
+
+    /// Returns the indexes of the blocks that have an edge to `idx`
+    pub fn predecessors(&self, idx: usize) -> Vec<usize> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| block.edges.iter().any(|edge| edge.to == idx))
+            .map(|(from, _)| from)
+            .collect()
+    }
+
+    /// Graphviz DOT, each block shows its address and source lines
+    pub fn to_dot(&self, program: &Program) -> String {
+        let mut output = String::from("digraph program {\n");
+        output.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for (idx, block) in self.blocks.iter().enumerate() {
+            let mut label = format!("${:04X}\\l", block.address);
+            for line in &program.lines[block.lines.clone()] {
+                label.push_str(&escape_dot(line.line.original.trim()));
+                label.push_str("\\l");
+            }
+            output.push_str(&format!("    b{idx} [label=\"{label}\"];\n"));
+        }
+        for (idx, block) in self.blocks.iter().enumerate() {
+            for edge in &block.edges {
+                let style = match edge.kind {
+                    EdgeKind::Next => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Branch => " [label=\"branch\", color=\"blue\"]",
+                    EdgeKind::Call => " [label=\"call\", style=\"dashed\"]",
+                };
+                output.push_str(&format!("    b{idx} -> b{}{style};\n", edge.to));
+            }
+        }
+        output.push_str("}\n");
+        output
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_program;
+
+    #[test]
+    fn test_flow() {
+        let program = parse_program(&[
+            "nop",
+            "jmp $0",
+            "je $0",
+            "jmp ax",
+            "call $0",
+            "ret",
+            "reti",
+            "halt",
+            "ehalt",
+            "jrf 4",
+            "jrb 2",
+            "jbc ax, 1",
+            ".db 4",
+        ])
+        .unwrap();
+        let flows: Vec<Flow> = program.lines.iter().map(Flow::of).collect();
+        assert_eq!(
+            flows,
+            vec![
+                Flow::Next,
+                Flow::Jump(Some(0)),
+                Flow::Branch(Some(0)),
+                Flow::Jump(None),
+                Flow::Call(Some(0)),
+                Flow::Return,
+                Flow::Return,
+                Flow::Halt,
+                Flow::Halt,
+                Flow::Jump(Some(20)),
+                Flow::Jump(Some(16)),
+                Flow::Branch(None),
+                Flow::Next,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_graph() {
+        let program = parse_program(&[
+            "main: cpy.b al, 0",
+            ".loop: inc.b al",
+            "  cmp.b al, 10",
+            "  jne .loop",
+            "  call func",
+            "  halt",
+            "func: jmp ax",
+        ])
+        .unwrap();
+        let graph = ControlFlowGraph::new(&program);
+        let summary: Vec<(Range<usize>, u16, Flow, Vec<Edge>)> = graph
+            .blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.lines.clone(),
+                    block.address,
+                    block.flow,
+                    block.edges.clone(),
+                )
+            })
+            .collect();
+        let edge = |to, kind| Edge { to, kind };
+        assert_eq!(
+            summary,
+            vec![
+                (0..1, 0, Flow::Next, vec![edge(1, EdgeKind::Next)]),
+                (
+                    1..4,
+                    3,
+                    Flow::Branch(Some(3)),
+                    vec![edge(1, EdgeKind::Branch), edge(2, EdgeKind::Next)]
+                ),
+                (
+                    4..5,
+                    11,
+                    Flow::Call(Some(15)),
+                    vec![edge(4, EdgeKind::Call), edge(3, EdgeKind::Next)]
+                ),
+                (5..6, 14, Flow::Halt, vec![]),
+                (6..7, 15, Flow::Jump(None), vec![]),
+            ]
+        );
+        assert_eq!(graph.blocks[0].label, Some(String::from("main")));
+        assert_eq!(graph.blocks[1].label, Some(String::from("main.loop")));
+        assert_eq!(graph.blocks[1].size, 8);
+        assert_eq!(graph.predecessors(1), vec![0, 1]);
+        assert!(graph.blocks[4].has_unresolved_target(&graph));
+        assert!(!graph.blocks[1].has_unresolved_target(&graph));
+
+        let dot = graph.to_dot(&program);
+        assert!(dot.starts_with("digraph program {\n"));
+        assert!(dot.contains("    b0 [label=\"$0000\\lmain: cpy.b al, 0\\l\"];\n"));
+        assert!(dot.contains("    b1 -> b1 [label=\"branch\", color=\"blue\"];\n"));
+        assert!(dot.contains("    b2 -> b4 [label=\"call\", style=\"dashed\"];\n"));
+        assert!(dot.contains("    b0 -> b1;\n"));
+    }
+
+    #[test]
+    fn test_jump_targets() {
+        //jump to an address without a label splits the block
+        let program = parse_program(&["nop", "nop", "jmp $1"]).unwrap();
+        let graph = ControlFlowGraph::new(&program);
+        assert_eq!(graph.blocks.len(), 2);
+        assert_eq!(graph.blocks[1].lines, 1..3);
+        assert_eq!(
+            graph.blocks[1].edges,
+            vec![Edge {
+                to: 1,
+                kind: EdgeKind::Jump
+            }]
+        );
+        //empty program
+        let graph = ControlFlowGraph::new(&parse_program(&[]).unwrap());
+        assert!(graph.blocks.is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+use crate::control_flow::ControlFlowGraph;
+use crate::warnings::{code_span, warning, Warning, WarningKind};
+use crate::Program;
+use std::collections::HashSet;
+
+/// Finds code that can't run and labels that are never used
+///
+/// Returns `unreachable_code` and `unused_label` warnings
+pub fn dead_code(lines: &[&str], program: &Program) -> Vec<Warning> {
+    let mut warnings = vec![];
+    unreachable_code(lines, program, &mut warnings);
+    unused_labels(lines, program, &mut warnings);
+    warnings.sort_by_key(|warning| (warning.line_num, warning.span.start));
+    warnings
+}
+
+/// Returns the indexes (in `program.lines`) of instructions that can't be reached from the entry
+/// point or any label, e.g. code after `JMP` or `HALT`
+///
+/// Labels are treated as reachable as they may be used by interrupts or jumps via registers
+pub fn unreachable_lines(program: &Program) -> Vec<usize> {
+    let graph = ControlFlowGraph::new(program);
+    let mut reachable = vec![false; graph.blocks.len()];
+    let mut pending: Vec<usize> = graph
+        .blocks
+        .iter()
+        .enumerate()
+        .filter(|(idx, block)| *idx == 0 || block.label.is_some())
+        .map(|(idx, _)| idx)
+        .collect();
+    while let Some(idx) = pending.pop() {
+        if reachable[idx] {
+            continue;
+        }
+        reachable[idx] = true;
+        pending.extend(graph.blocks[idx].edges.iter().map(|edge| edge.to));
+    }
+    graph
+        .blocks
+        .iter()
+        .zip(reachable)
+        .filter(|(_, reachable)| !reachable)
+        .flat_map(|(block, _)| block.lines.clone())
+        .filter(|idx| is_instruction(program, *idx))
+        .collect()
+}
+
+fn is_instruction(program: &Program, idx: usize) -> bool {
+    program.lines[idx]
+        .line
+        .command
+        .as_ref()
+        .map(|(op, _)| !op.starts_with('.'))
+        .unwrap_or_default()
+}
+
+fn unreachable_code(lines: &[&str], program: &Program, warnings: &mut Vec<Warning>) {
+    let unreachable = unreachable_lines(program);
+    for idx in &unreachable {
+        let previous = (0..*idx).rev().find(|prev| is_instruction(program, *prev));
+        //only the first line of each unreachable section is reported
+        if let Some(prev) = previous {
+            if unreachable.contains(&prev) {
+                continue;
+            }
+        }
+        let line_num = program.lines[*idx].line.num;
+        let message = match previous.and_then(|prev| {
+            let line = &program.lines[prev];
+            line.line
+                .command
+                .as_ref()
+                .map(|(op, _)| (op.to_ascii_uppercase(), line.line.num))
+        }) {
+            Some((op, prev_num)) => format!("Unreachable code after {op} on line {prev_num}"),
+            None => String::from("Unreachable code"),
+        };
+        warnings.push(warning(
+            line_num,
+            code_span(lines, line_num),
+            WarningKind::UnreachableCode,
+            message,
+        ));
+    }
+}
+
+fn unused_labels(lines: &[&str], program: &Program, warnings: &mut Vec<Warning>) {
+    let mut used = HashSet::new();
+    let mut scope: Option<&String> = None;
+    for line in &program.lines {
+        if let Some(label) = &line.line.label {
+            if program.labels.contains_key(label) && !label.contains('.') {
+                scope = Some(label);
+            }
+        }
+        if let Some((_, args)) = &line.line.command {
+            for arg in args {
+                if arg.starts_with(['"', '\'']) {
+                    continue;
+                }
+                let is_symbol_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+                for token in arg.split(|c: char| !is_symbol_char(c)) {
+                    if token.starts_with('.') {
+                        if let Some(scope) = scope {
+                            used.insert(format!("{scope}{token}"));
+                        }
+                    } else {
+                        used.insert(token.to_string());
+                    }
+                }
+            }
+        }
+    }
+    for line in &program.lines {
+        if let Some(label) = &line.line.label {
+            if line.address == 0 || !program.labels.contains_key(label) || used.contains(label) {
+                continue;
+            }
+            //local labels are written without the scope
+            let text = label.rfind('.').map(|idx| &label[idx..]).unwrap_or(label);
+            let code = code_span(lines, line.line.num);
+            warnings.push(warning(
+                line.line.num,
+                code.start..code.start + text.len(),
+                WarningKind::UnusedLabel,
+                format!("Label {label} is never used"),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_program;
+
+    fn unreachable(lines: &[&str]) -> Vec<usize> {
+        let program = parse_program(lines).unwrap();
+        unreachable_lines(&program)
+            .into_iter()
+            .map(|idx| program.lines[idx].line.num)
+            .collect()
+    }
+
+    #[test]
+    fn test_unreachable_lines() {
+        assert_eq!(
+            unreachable(&[
+                "main: jmp .skip",
+                "  inc.b al",
+                "  .db 1",
+                "  inc.b ah",
+                ".skip: jne .other",
+                "  ret",
+                "  nop",
+                "  @@: jmp ax",
+                "  nop",
+                ".other: halt",
+                "  halt",
+            ]),
+            vec![1, 3, 6, 8, 10]
+        );
+        assert!(unreachable(&["nop", "je $1", "halt"]).is_empty());
+        assert!(unreachable(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_dead_code() {
+        let lines = ["main: jmp main", "nop", "inc.b al", "unused: ret", "halt"];
+        let warnings = dead_code(&lines, &parse_program(&lines).unwrap());
+        let result: Vec<(usize, WarningKind, &str)> = warnings
+            .iter()
+            .map(|warning| (warning.line_num, warning.kind, warning.message.as_str()))
+            .collect();
+        assert_eq!(
+            result,
+            vec![
+                (
+                    1,
+                    WarningKind::UnreachableCode,
+                    "Unreachable code after JMP on line 0"
+                ),
+                (3, WarningKind::UnusedLabel, "Label unused is never used"),
+                (
+                    4,
+                    WarningKind::UnreachableCode,
+                    "Unreachable code after RET on line 3"
+                ),
+            ]
+        );
+    }
+}
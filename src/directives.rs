@@ -0,0 +1,567 @@
+use crate::interpreter::{split_args, strip_comment};
+use crate::lexer::split_at_punct;
+use crate::parsers::detect_value;
+use crate::strings::{decode_string, parse_char_literal, parse_string, StrChar};
+use crate::symbols::{is_valid_symbol_name, Symbols, HIDDEN_PREFIX};
+use crate::ParseError::*;
+use crate::{BlockKind, DirectiveReason, ParseError};
+use maikor_platform::registers::id;
+
+const STRUCT: &str = ".STRUCT";
+const STRUCT_END: &str = ".ENDS";
+const FIELD_BYTE: &str = ".B";
+const FIELD_WORD: &str = ".W";
+const STRUCT_SIZE: &str = "SIZE";
+pub const ENUM: &str = ".ENUM";
+pub const ENUM_END: &str = ".ENDE";
+const DATA_BYTES: &str = ".DB";
+const DATA_WORDS: &str = ".DW";
+const STRING_ZERO: &str = ".STRZ";
+const STRING_LENGTH: &str = ".STRL";
+const CHARMAP: &str = ".CHARMAP";
+
+/// Line number and text of lines that need to be assembled
+pub type CodeLines<'a> = Vec<(usize, &'a str)>;
+
+enum Block {
+    Struct(StructDef),
+    Enum(EnumDef),
+}
+
+struct StructDef {
+    line_num: usize,
+    name: String,
+    size: u16,
+}
+
+struct EnumDef {
+    line_num: usize,
+    name: String,
+    /// Value for the next member, None if the last member was 65535
+    next: Option<u16>,
+}
+
+/// Reads all definition directives (such as `.struct`, `.enum` and `.charmap`) into a symbol table
+///
+/// Returns the symbols and the remaining lines (with their line numbers) that contain code
+pub fn read_definitions<'a>(
+    lines: &[&'a str],
+    mut symbols: Symbols,
+) -> Result<(Symbols, CodeLines<'a>), ParseError> {
+    let mut code = vec![];
+    let mut current: Option<Block> = None;
+    for (line_num, line) in lines.iter().enumerate() {
+        let trimmed = strip_comment(line);
+        if trimmed.is_empty() {
+            continue;
+        }
+        let (directive, params) = trimmed
+            .split_once(char::is_whitespace)
+            .map(|(directive, params)| (directive, params.trim()))
+            .unwrap_or((trimmed, ""));
+        let directive = directive.to_ascii_uppercase();
+        match current.as_mut() {
+            Some(Block::Struct(def)) => {
+                if directive == STRUCT_END {
+                    symbols.add_constant(
+                        line_num,
+                        &format!("{}.{STRUCT_SIZE}", def.name),
+                        def.size,
+                    )?;
+                    current = None;
+                } else {
+                    read_struct_field(line_num, trimmed, &directive, params, def, &mut symbols)?;
+                }
+            }
+            Some(Block::Enum(def)) => {
+                if directive == ENUM_END {
+                    current = None;
+                } else {
+                    read_enum_member(line_num, trimmed, def, &mut symbols)?;
+                }
+            }
+            None => match directive.as_str() {
+                STRUCT => {
+                    let name = read_name(line_num, trimmed, params)?;
+                    current = Some(Block::Struct(StructDef {
+                        line_num,
+                        name: name.to_string(),
+                        size: 0,
+                    }));
+                }
+                ENUM => {
+                    let (name, start) = match split_at_punct(params, ',') {
+                        Some((name, start)) => (
+                            name.trim(),
+                            read_value(line_num, trimmed, start.trim(), &symbols)?,
+                        ),
+                        None => (params, 0),
+                    };
+                    let name = read_name(line_num, trimmed, name)?;
+                    current = Some(Block::Enum(EnumDef {
+                        line_num,
+                        name: name.to_string(),
+                        next: Some(start),
+                    }));
+                }
+                CHARMAP => read_charmap(line_num, trimmed, params, &mut symbols)?,
+                STRUCT_END | ENUM_END => {
+                    return Err(InvalidDirective(
+                        line_num,
+                        trimmed.to_string(),
+                        DirectiveReason::NoBlockToEnd,
+                    ));
+                }
+                _ => code.push((line_num, *line)),
+            },
+        }
+    }
+    match current {
+        Some(Block::Struct(def)) => {
+            Err(UnterminatedBlock(def.line_num, def.name, BlockKind::Struct))
+        }
+        Some(Block::Enum(def)) => Err(UnterminatedBlock(def.line_num, def.name, BlockKind::Enum)),
+        None => Ok((symbols, code)),
+    }
+}
+
+/// Converts data directives (`.db`, `.dw`, `.strz` and `.strl`) into bytes
+pub fn read_data(
+    line_num: usize,
+    directive: &str,
+    args: &[String],
+    symbols: &Symbols,
+) -> Result<Vec<u8>, ParseError> {
+    let original = format!("{directive} {}", args.join(", "));
+    let upper = directive.to_ascii_uppercase();
+    if ![DATA_BYTES, DATA_WORDS, STRING_ZERO, STRING_LENGTH].contains(&upper.as_str()) {
+        return Err(InvalidDirective(
+            line_num,
+            directive.to_string(),
+            DirectiveReason::Unknown,
+        ));
+    }
+    if args.is_empty() {
+        return Err(InvalidDirective(
+            line_num,
+            original,
+            DirectiveReason::MissingValues,
+        ));
+    }
+    let mut bytes = vec![];
+    for arg in args {
+        let is_string = arg.starts_with('"');
+        match upper.as_str() {
+            DATA_BYTES => {
+                if is_string {
+                    bytes.extend_from_slice(&parse_string(line_num, arg, symbols.charmap())?);
+                } else {
+                    let value = read_value(line_num, &original, arg, symbols)?;
+                    if value > u8::MAX as u16 {
+                        return Err(NumberMustBeByte(line_num, arg.to_string()));
+                    }
+                    bytes.push(value as u8);
+                }
+            }
+            DATA_WORDS => {
+                if is_string {
+                    return Err(InvalidDirective(
+                        line_num,
+                        original,
+                        DirectiveReason::StringInWords,
+                    ));
+                }
+                let value = read_value(line_num, &original, arg, symbols)?;
+                bytes.extend_from_slice(&value.to_be_bytes());
+            }
+            _ => {
+                if !is_string {
+                    return Err(InvalidDirective(
+                        line_num,
+                        original,
+                        DirectiveReason::NotString(arg.to_string()),
+                    ));
+                }
+                let text = parse_string(line_num, arg, symbols.charmap())?;
+                if upper == STRING_ZERO {
+                    bytes.extend_from_slice(&text);
+                    bytes.push(0);
+                } else {
+                    if text.len() > u8::MAX as usize {
+                        return Err(InvalidDirective(
+                            line_num,
+                            original,
+                            DirectiveReason::StringTooLong,
+                        ));
+                    }
+                    bytes.push(text.len() as u8);
+                    bytes.extend_from_slice(&text);
+                }
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// Charmaps are `'c', value` or `"chars", first value`
+fn read_charmap(
+    line_num: usize,
+    original: &str,
+    params: &str,
+    symbols: &mut Symbols,
+) -> Result<(), ParseError> {
+    let invalid = |reason| InvalidDirective(line_num, original.to_string(), reason);
+    let args = split_args(params);
+    if args.len() != 2 {
+        return Err(invalid(DirectiveReason::CharmapFormat));
+    }
+    let chars = if let Some(chr) = parse_char_literal(&args[0]) {
+        vec![chr]
+    } else if args[0].starts_with('"') {
+        decode_string(line_num, &args[0])?
+            .into_iter()
+            .map(|chr| match chr {
+                StrChar::Char(chr) => Ok(chr),
+                StrChar::Byte(_) => Err(invalid(DirectiveReason::UnmappableEscape)),
+            })
+            .collect::<Result<Vec<char>, ParseError>>()?
+    } else {
+        return Err(invalid(DirectiveReason::NotCharOrString(args[0].clone())));
+    };
+    let first = read_value(line_num, original, &args[1], symbols)?;
+    for (offset, chr) in chars.into_iter().enumerate() {
+        let value = first as usize + offset;
+        if value > u8::MAX as usize {
+            return Err(NumberMustBeByte(line_num, original.to_string()));
+        }
+        symbols.map_char(chr, value as u8);
+    }
+    Ok(())
+}
+
+fn read_struct_field(
+    line_num: usize,
+    original: &str,
+    directive: &str,
+    params: &str,
+    def: &mut StructDef,
+    symbols: &mut Symbols,
+) -> Result<(), ParseError> {
+    let field_size: u16 = match directive {
+        FIELD_BYTE => 1,
+        FIELD_WORD => 2,
+        _ => {
+            return Err(InvalidDirective(
+                line_num,
+                original.to_string(),
+                DirectiveReason::InvalidField(def.name.clone()),
+            ))
+        }
+    };
+    let (name, count) = read_field(line_num, original, params)?;
+    symbols.add_constant(line_num, &format!("{}.{name}", def.name), def.size)?;
+    def.size = field_size
+        .checked_mul(count)
+        .and_then(|size| def.size.checked_add(size))
+        .ok_or_else(|| {
+            InvalidDirective(
+                line_num,
+                original.to_string(),
+                DirectiveReason::StructTooBig,
+            )
+        })?;
+    Ok(())
+}
+
+/// Members are `name` or `name = value`
+fn read_enum_member(
+    line_num: usize,
+    original: &str,
+    def: &mut EnumDef,
+    symbols: &mut Symbols,
+) -> Result<(), ParseError> {
+    let (name, value) = match split_at_punct(original, '=') {
+        Some((name, value)) => (
+            name.trim(),
+            read_value(line_num, original, value.trim(), symbols)?,
+        ),
+        None => match def.next {
+            Some(value) => (original, value),
+            None => {
+                return Err(InvalidDirective(
+                    line_num,
+                    original.to_string(),
+                    DirectiveReason::EnumTooBig,
+                ))
+            }
+        },
+    };
+    if name.contains('.') {
+        return Err(InvalidDirective(
+            line_num,
+            original.to_string(),
+            DirectiveReason::DotInMember(name.to_string()),
+        ));
+    }
+    let name = read_name(line_num, original, name)?;
+    symbols.add_constant(line_num, &format!("{}.{name}", def.name), value)?;
+    def.next = value.checked_add(1);
+    Ok(())
+}
+
+fn read_value(
+    line_num: usize,
+    original: &str,
+    value: &str,
+    symbols: &Symbols,
+) -> Result<u16, ParseError> {
+    detect_value(line_num, original, value, symbols)?.ok_or_else(|| {
+        InvalidDirective(
+            line_num,
+            original.to_string(),
+            DirectiveReason::NotNumber(value.to_string()),
+        )
+    })
+}
+
+fn read_name<'a>(line_num: usize, original: &str, name: &'a str) -> Result<&'a str, ParseError> {
+    if !is_valid_symbol_name(name) {
+        Err(InvalidDirective(
+            line_num,
+            original.to_string(),
+            DirectiveReason::InvalidName(name.to_string()),
+        ))
+    } else if id::from_name(name).is_ok() {
+        Err(InvalidDirective(
+            line_num,
+            original.to_string(),
+            DirectiveReason::RegisterName(name.to_string()),
+        ))
+    } else if name.starts_with(HIDDEN_PREFIX) {
+        Err(InvalidDirective(
+            line_num,
+            original.to_string(),
+            DirectiveReason::ReservedName(name.to_string()),
+        ))
+    } else {
+        Ok(name)
+    }
+}
+
+/// Fields are `name` or `name, count`
+fn read_field<'a>(
+    line_num: usize,
+    original: &str,
+    params: &'a str,
+) -> Result<(&'a str, u16), ParseError> {
+    let (name, count) = match split_at_punct(params, ',') {
+        Some((name, count)) => {
+            let count = count.trim();
+            let count = count.parse::<u16>().map_err(|_| {
+                InvalidDirective(
+                    line_num,
+                    original.to_string(),
+                    DirectiveReason::InvalidCount(count.to_string()),
+                )
+            })?;
+            (name.trim(), count)
+        }
+        None => (params, 1),
+    };
+    if name.contains('.') {
+        return Err(InvalidDirective(
+            line_num,
+            original.to_string(),
+            DirectiveReason::DotInField(name.to_string()),
+        ));
+    }
+    Ok((read_name(line_num, original, name)?, count))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_struct() {
+        let lines = vec![
+            ".struct Enemy # enemy data",
+            "  .b hp",
+            "  .w pos",
+            "",
+            "  .B name, 8",
+            ".ends",
+            "INC.B AL",
+        ];
+        let (symbols, code) = read_definitions(&lines, Symbols::default()).unwrap();
+        assert_eq!(symbols.constant("Enemy.hp"), Some(0));
+        assert_eq!(symbols.constant("Enemy.pos"), Some(1));
+        assert_eq!(symbols.constant("Enemy.name"), Some(3));
+        assert_eq!(symbols.constant("Enemy.SIZE"), Some(11));
+        assert_eq!(code, vec![(6, "INC.B AL")]);
+    }
+
+    #[test]
+    fn test_invalid_structs() {
+        assert!(read_definitions(&[".struct Enemy", ".b hp"], Symbols::default()).is_err());
+        assert!(read_definitions(
+            &[".struct Enemy", ".b hp", ".b hp", ".ends"],
+            Symbols::default()
+        )
+        .is_err());
+        assert!(
+            read_definitions(&[".struct Enemy", "INC.B AL", ".ends"], Symbols::default()).is_err()
+        );
+        assert!(read_definitions(&[".struct AX", ".ends"], Symbols::default()).is_err());
+        assert!(read_definitions(&[".struct 1a", ".ends"], Symbols::default()).is_err());
+        assert!(read_definitions(&[".struct A", ".b a.b", ".ends"], Symbols::default()).is_err());
+        assert!(
+            read_definitions(&[".struct A", ".w a, 40000", ".ends"], Symbols::default()).is_err()
+        );
+        assert!(read_definitions(&[".ends"], Symbols::default()).is_err());
+    }
+
+    fn data(directive: &str, args: &[&str]) -> Result<Vec<u8>, ParseError> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        read_data(0, directive, &args, &Symbols::default())
+    }
+
+    #[test]
+    fn test_directive_reasons() {
+        let err = |lines: &[&str]| read_definitions(lines, Symbols::default()).unwrap_err();
+        assert!(matches!(
+            err(&[".struct Enemy", ".b hp"]),
+            UnterminatedBlock(0, _, BlockKind::Struct)
+        ));
+        assert!(matches!(
+            err(&[".enum Dir"]),
+            UnterminatedBlock(0, _, BlockKind::Enum)
+        ));
+        assert!(matches!(
+            err(&[".struct A", ".w a, 40000", ".ends"]),
+            InvalidDirective(1, _, DirectiveReason::StructTooBig)
+        ));
+        assert!(matches!(
+            err(&[".struct A", ".b a, x", ".ends"]),
+            InvalidDirective(1, _, DirectiveReason::InvalidCount(count)) if count == "x"
+        ));
+        assert!(matches!(
+            err(&[".struct AX", ".ends"]),
+            InvalidDirective(0, _, DirectiveReason::RegisterName(name)) if name == "AX"
+        ));
+        assert!(matches!(
+            err(&[".ende"]),
+            InvalidDirective(0, _, DirectiveReason::NoBlockToEnd)
+        ));
+        assert_eq!(
+            err(&[".struct A", ".b a, x", ".ends"]).to_string(),
+            "Invalid directive .b a, x on line 1: 'x' is not a valid count, must be 0 - 65535"
+        );
+    }
+
+    #[test]
+    fn test_data() {
+        assert_eq!(data(".db", &["1", "x10", "'A'"]).unwrap(), vec![1, 16, 65]);
+        assert_eq!(
+            data(".DB", &["\"Hi\\n\"", "0"]).unwrap(),
+            vec![72, 105, 10, 0]
+        );
+        assert_eq!(data(".dw", &["1", "xFFFE"]).unwrap(), vec![0, 1, 255, 254]);
+        assert_eq!(
+            data(".strz", &["\"Hi\"", "\"\""]).unwrap(),
+            vec![72, 105, 0, 0]
+        );
+        assert_eq!(data(".strl", &["\"Hi\""]).unwrap(), vec![2, 72, 105]);
+
+        assert!(data(".db", &[]).is_err());
+        assert!(data(".db", &["256"]).is_err());
+        assert!(data(".db", &["\"é\""]).is_err());
+        assert!(data(".dw", &["\"Hi\""]).is_err());
+        assert!(data(".strz", &["1"]).is_err());
+        assert!(data(".strl", &[&format!("\"{}\"", "a".repeat(256))]).is_err());
+        assert!(data(".foo", &["1"]).is_err());
+    }
+
+    #[test]
+    fn test_charmap() {
+        let lines = vec![
+            ".charmap \"ABC\", 10",
+            ".charmap ',', 0",
+            ".charmap ' ', x0F",
+        ];
+        let (symbols, _) = read_definitions(&lines, Symbols::default()).unwrap();
+        let charmap = symbols.charmap().unwrap();
+        assert_eq!(charmap.len(), 5);
+        assert_eq!(charmap[&'A'], 10);
+        assert_eq!(charmap[&'B'], 11);
+        assert_eq!(charmap[&'C'], 12);
+        assert_eq!(charmap[&','], 0);
+        assert_eq!(charmap[&' '], 15);
+
+        let args = vec![String::from("\"CAB, \"")];
+        assert_eq!(
+            read_data(0, ".db", &args, &symbols).unwrap(),
+            vec![12, 10, 11, 0, 15]
+        );
+        let args = vec![String::from("\"D\"")];
+        assert!(read_data(0, ".db", &args, &symbols).is_err());
+
+        let invalid = |line: &str| read_definitions(&[line], Symbols::default()).is_err();
+        assert!(invalid(".charmap 'A'"));
+        assert!(invalid(".charmap A, 1"));
+        assert!(invalid(".charmap \"AB\", 255"));
+        assert!(invalid(".charmap \"\\x00\", 1"));
+    }
+
+    #[test]
+    fn test_enum() {
+        let lines = vec![
+            ".enum State",
+            "  idle",
+            "  walking # comment",
+            "  dead = x10",
+            "  gone",
+            ".ende",
+            ".enum Dir, 5",
+            "  up",
+            "  down",
+            "  last = State.gone",
+            ".ende",
+            ".enum Sym, '='",
+            "  equals",
+            "  comma = ','",
+            ".ende",
+        ];
+        let (symbols, code) = read_definitions(&lines, Symbols::default()).unwrap();
+        assert_eq!(symbols.constant("State.idle"), Some(0));
+        assert_eq!(symbols.constant("State.walking"), Some(1));
+        assert_eq!(symbols.constant("State.dead"), Some(16));
+        assert_eq!(symbols.constant("State.gone"), Some(17));
+        assert_eq!(symbols.constant("Dir.up"), Some(5));
+        assert_eq!(symbols.constant("Dir.down"), Some(6));
+        assert_eq!(symbols.constant("Dir.last"), Some(17));
+        assert_eq!(symbols.constant("Sym.equals"), Some(b'=' as u16));
+        assert_eq!(symbols.constant("Sym.comma"), Some(b',' as u16));
+        assert!(code.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_enums() {
+        assert!(read_definitions(&[".enum State", "idle"], Symbols::default()).is_err());
+        assert!(read_definitions(
+            &[".enum State", "idle", "idle", ".ende"],
+            Symbols::default()
+        )
+        .is_err());
+        assert!(read_definitions(&[".enum State", "a = b", ".ende"], Symbols::default()).is_err());
+        assert!(read_definitions(&[".enum State", "a.b", ".ende"], Symbols::default()).is_err());
+        assert!(read_definitions(
+            &[".enum State", "a = 65535", "b", ".ende"],
+            Symbols::default()
+        )
+        .is_err());
+        assert!(read_definitions(&[".enum State, 70000", ".ende"], Symbols::default()).is_err());
+        assert!(read_definitions(&[".enum State", ".ends"], Symbols::default()).is_err());
+        assert!(read_definitions(&[".ende"], Symbols::default()).is_err());
+    }
+}
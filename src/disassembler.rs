@@ -0,0 +1,190 @@
+use crate::arg_patterns::ARG_MATCHES;
+use crate::forms::{op_forms, ArgKind, OpForm};
+use lazy_static::lazy_static;
+use maikor_platform::op_params::*;
+use maikor_platform::registers::id;
+use std::collections::HashMap;
+
+lazy_static! {
+    //format is Map<op_code, form>
+    static ref FORMS: HashMap<u8, OpForm> = make_table();
+}
+
+/// Forms with indirect registers share op codes with register forms, so those are skipped
+/// and the register mode decides if it's indirect
+fn make_table() -> HashMap<u8, OpForm> {
+    let mut names: Vec<&&str> = ARG_MATCHES.keys().collect();
+    names.sort();
+    let mut table = HashMap::new();
+    for name in names {
+        for form in op_forms(name).unwrap_or_default() {
+            if !form.args.contains(&ArgKind::IndirectReg) {
+                table.entry(form.op_code).or_insert(form);
+            }
+        }
+    }
+    table
+}
+
+/// An instruction (or byte of data if the op code is unknown) from `disassemble`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DisassembledLine {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// Source that assembles to `bytes`, e.g. `ADD.B (bx + 4), 1` or `.db xFF`
+    pub text: String,
+}
+
+/// Converts bytes back into source, addresses are written as `$xXXXX` (labels aren't known)
+///
+/// Unknown op codes, invalid registers and incomplete instructions are written as `.db`
+pub fn disassemble(bytes: &[u8]) -> Vec<DisassembledLine> {
+    let mut output = vec![];
+    let mut idx = 0;
+    while idx < bytes.len() && idx <= u16::MAX as usize {
+        let (size, text) = match decode(&bytes[idx..]) {
+            Some(result) => result,
+            None => (1, format!(".db x{:02X}", bytes[idx])),
+        };
+        output.push(DisassembledLine {
+            address: idx as u16,
+            bytes: bytes[idx..idx + size].to_vec(),
+            text,
+        });
+        idx += size;
+    }
+    output
+}
+
+/// Returns the size and text of the instruction at the start of `bytes`
+fn decode(bytes: &[u8]) -> Option<(usize, String)> {
+    let form = FORMS.get(bytes.first()?)?;
+    let mut idx = 1;
+    let mut read = |count: usize| {
+        let value = bytes.get(idx..idx + count)?;
+        idx += count;
+        Some(
+            value
+                .iter()
+                .fold(0_u16, |acc, byte| (acc << 8) | *byte as u16),
+        )
+    };
+    //(kind, value)
+    let mut values = vec![];
+    for kind in &form.args {
+        values.push((*kind, read(kind.size())?));
+    }
+    let mut args = vec![];
+    for (kind, value) in values {
+        let text = match kind {
+            ArgKind::Address => format!("$x{value:04X}"),
+            ArgKind::Word | ArgKind::Byte => value.to_string(),
+            _ => {
+                let reg = value as u8;
+                let offset = match reg & MASK {
+                    IND_OFFSET_REG | IND_OFFSET_EXT_REG => Some(register_name(read(1)? as u8)?),
+                    IND_OFFSET_NUM => Some(read(2)?.to_string()),
+                    _ => None,
+                };
+                register(reg, offset)?
+            }
+        };
+        args.push(text);
+    }
+    let text = if args.is_empty() {
+        form.op_name.to_string()
+    } else {
+        format!("{} {}", form.op_name, args.join(", "))
+    };
+    Some((idx, text))
+}
+
+fn register_name(reg: u8) -> Option<String> {
+    id::to_name(reg).ok().map(str::to_ascii_lowercase)
+}
+
+/// `reg` includes the mode, e.g. `IND_POST_INC`, and `offset` is the text of the offset
+fn register(reg: u8, offset: Option<String>) -> Option<String> {
+    let name = register_name(reg & !MASK)?;
+    let text = match (reg & MASK, offset) {
+        (REGISTER, _) => name,
+        (POST_INC, _) => format!("{name}+"),
+        (POST_DEC, _) => format!("{name}-"),
+        (PRE_INC, _) => format!("+{name}"),
+        (PRE_DEC, _) => format!("-{name}"),
+        (INDIRECT, _) => format!("({name})"),
+        (IND_OFFSET_REG | IND_OFFSET_EXT_REG | IND_OFFSET_NUM, Some(offset)) => {
+            format!("({name} + {offset})")
+        }
+        (IND_POST_INC, _) => format!("({name})+"),
+        (IND_POST_DEC, _) => format!("({name})-"),
+        (IND_PRE_INC, _) => format!("+({name})"),
+        (IND_PRE_DEC, _) => format!("-({name})"),
+        _ => return None,
+    };
+    Some(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_program;
+
+    #[test]
+    fn test_disassemble() {
+        let lines = [
+            "NOP",
+            "ADD.B al, 1",
+            "CPY.W $x0100, ax",
+            "ADD.B (bx + 4), 1",
+            "SUB.W (cx + dx), -(bx)",
+            "INC.B (ax + bl)",
+            "CPY.B (bx)+, al-",
+            "JMP $x0000",
+            "HALT",
+        ];
+        let program = parse_program(&lines).unwrap();
+        let output = disassemble(&program.bytes);
+        let text: Vec<&str> = output.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(text, lines);
+        for (line, parsed) in output.iter().zip(&program.lines) {
+            assert_eq!(line.address, parsed.address);
+            assert_eq!(line.bytes, parsed.bytes);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        use maikor_platform::ops::{INC_REG_BYTE, JMP_ADDR};
+        let first = |bytes: &[u8]| disassemble(bytes)[0].text.clone();
+        let unknown = (0..=u8::MAX)
+            .find(|code| !FORMS.contains_key(code))
+            .unwrap();
+        assert_eq!(first(&[unknown]), format!(".db x{unknown:02X}"));
+        assert_eq!(
+            first(&[INC_REG_BYTE, 13]),
+            format!(".db x{INC_REG_BYTE:02X}")
+        );
+        assert_eq!(first(&[JMP_ADDR, 1]), format!(".db x{JMP_ADDR:02X}"));
+        assert_eq!(disassemble(&[JMP_ADDR, 1]).len(), 2);
+        assert_eq!(disassemble(&[]), vec![]);
+    }
+
+    #[test]
+    fn test_all_op_codes_assemble() {
+        for (op_code, form) in FORMS.iter() {
+            let mut bytes = vec![*op_code];
+            for kind in &form.args {
+                match kind {
+                    ArgKind::Address | ArgKind::Word => bytes.extend_from_slice(&[1, 0]),
+                    ArgKind::ExtReg => bytes.push(id::AX),
+                    _ => bytes.push(id::AL),
+                }
+            }
+            let output = disassemble(&bytes);
+            assert_eq!(output.len(), 1, "{form}");
+            let program = parse_program(&[output[0].text.as_str()]).unwrap();
+            assert_eq!(program.bytes[0], *op_code, "{}", output[0].text);
+        }
+    }
+}
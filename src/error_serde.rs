@@ -0,0 +1,374 @@
+use crate::errors::int_error_serde;
+use crate::{
+    ArgKind, BlockKind, DirectiveReason, GeneralReason, LabelReason, ParseError, RegisterReason,
+    StringReason, WarningKind,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::num::ParseIntError;
+
+/// `ParseError` with named fields, so the JSON doesn't depend on the order of the tuple fields
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", content = "fields")]
+enum ErrorFields {
+    EmptyLine,
+    General {
+        line: usize,
+        text: String,
+        reason: GeneralReason,
+    },
+    AddressHexFormat {
+        line: usize,
+        text: String,
+        #[serde(with = "int_error_serde")]
+        error: ParseIntError,
+    },
+    AddressNumFormat {
+        line: usize,
+        text: String,
+        #[serde(with = "int_error_serde")]
+        error: ParseIntError,
+    },
+    AddressTooBig {
+        line: usize,
+        text: String,
+    },
+    NumberFormat {
+        line: usize,
+        text: String,
+        #[serde(with = "int_error_serde")]
+        error: ParseIntError,
+    },
+    NumberHexFormat {
+        line: usize,
+        text: String,
+        #[serde(with = "int_error_serde")]
+        error: ParseIntError,
+    },
+    NumberTooBig {
+        line: usize,
+        text: String,
+    },
+    InvalidRegister {
+        line: usize,
+        text: String,
+        reason: RegisterReason,
+    },
+    SignedNumberNumFormat {
+        line: usize,
+        text: String,
+        #[serde(with = "int_error_serde")]
+        error: ParseIntError,
+    },
+    SignedNumberNumRange {
+        line: usize,
+        text: String,
+    },
+    NumberMustBeByte {
+        line: usize,
+        text: String,
+    },
+    InvalidOpCode {
+        line: usize,
+        op_code: u8,
+    },
+    InvalidArguments {
+        line: usize,
+        found: Vec<ArgKind>,
+        op: String,
+        forms: Vec<Vec<ArgKind>>,
+    },
+    MissingArguments {
+        line: usize,
+        op: String,
+        forms: Vec<Vec<ArgKind>>,
+    },
+    InvalidOpName {
+        line: usize,
+        op: String,
+    },
+    InvalidCharacter {
+        line: usize,
+        text: String,
+    },
+    InvalidOffset {
+        line: usize,
+        text: String,
+    },
+    InvalidDirective {
+        line: usize,
+        directive: String,
+        reason: DirectiveReason,
+    },
+    UnterminatedBlock {
+        line: usize,
+        block: String,
+        block_kind: BlockKind,
+    },
+    DuplicateSymbol {
+        line: usize,
+        name: String,
+    },
+    InvalidString {
+        line: usize,
+        text: String,
+        reason: StringReason,
+    },
+    InvalidStringCharacter {
+        line: usize,
+        text: String,
+        chr: char,
+    },
+    UnmappedCharacter {
+        line: usize,
+        text: String,
+        chr: char,
+    },
+    InvalidLabel {
+        line: usize,
+        label: String,
+        reason: LabelReason,
+    },
+    UnknownSymbol {
+        line: usize,
+        name: String,
+    },
+    ProgramTooBig {
+        line: usize,
+    },
+    DeniedWarning {
+        line: usize,
+        warning: WarningKind,
+        message: String,
+    },
+}
+
+impl From<&ParseError> for ErrorFields {
+    fn from(err: &ParseError) -> Self {
+        let line = err.line_num().unwrap_or_default();
+        match err {
+            ParseError::EmptyLine => ErrorFields::EmptyLine,
+            ParseError::General(_, value, reason) => ErrorFields::General {
+                line,
+                text: value.clone(),
+                reason: *reason,
+            },
+            ParseError::AddressHexFormat(_, value, error) => ErrorFields::AddressHexFormat {
+                line,
+                text: value.clone(),
+                error: error.clone(),
+            },
+            ParseError::AddressNumFormat(_, value, error) => ErrorFields::AddressNumFormat {
+                line,
+                text: value.clone(),
+                error: error.clone(),
+            },
+            ParseError::AddressTooBig(_, value) => ErrorFields::AddressTooBig {
+                line,
+                text: value.clone(),
+            },
+            ParseError::NumberFormat(_, value, error) => ErrorFields::NumberFormat {
+                line,
+                text: value.clone(),
+                error: error.clone(),
+            },
+            ParseError::NumberHexFormat(_, value, error) => ErrorFields::NumberHexFormat {
+                line,
+                text: value.clone(),
+                error: error.clone(),
+            },
+            ParseError::NumberTooBig(_, value) => ErrorFields::NumberTooBig {
+                line,
+                text: value.clone(),
+            },
+            ParseError::InvalidRegister(_, value, reason) => ErrorFields::InvalidRegister {
+                line,
+                text: value.clone(),
+                reason: reason.clone(),
+            },
+            ParseError::SignedNumberNumFormat(_, value, error) => {
+                ErrorFields::SignedNumberNumFormat {
+                    line,
+                    text: value.clone(),
+                    error: error.clone(),
+                }
+            }
+            ParseError::SignedNumberNumRange(_, value) => ErrorFields::SignedNumberNumRange {
+                line,
+                text: value.clone(),
+            },
+            ParseError::NumberMustBeByte(_, value) => ErrorFields::NumberMustBeByte {
+                line,
+                text: value.clone(),
+            },
+            ParseError::InvalidOpCode(_, op_code) => ErrorFields::InvalidOpCode {
+                line,
+                op_code: *op_code,
+            },
+            ParseError::InvalidArguments(_, found, op, forms) => ErrorFields::InvalidArguments {
+                line,
+                found: found.clone(),
+                op: op.clone(),
+                forms: forms.clone(),
+            },
+            ParseError::MissingArguments(_, op, forms) => ErrorFields::MissingArguments {
+                line,
+                op: op.clone(),
+                forms: forms.clone(),
+            },
+            ParseError::InvalidOpName(_, op) => ErrorFields::InvalidOpName {
+                line,
+                op: op.clone(),
+            },
+            ParseError::InvalidCharacter(_, value) => ErrorFields::InvalidCharacter {
+                line,
+                text: value.clone(),
+            },
+            ParseError::InvalidOffset(_, value) => ErrorFields::InvalidOffset {
+                line,
+                text: value.clone(),
+            },
+            ParseError::InvalidDirective(_, directive, reason) => ErrorFields::InvalidDirective {
+                line,
+                directive: directive.clone(),
+                reason: reason.clone(),
+            },
+            ParseError::UnterminatedBlock(_, block, block_kind) => ErrorFields::UnterminatedBlock {
+                line,
+                block: block.clone(),
+                block_kind: *block_kind,
+            },
+            ParseError::DuplicateSymbol(_, name) => ErrorFields::DuplicateSymbol {
+                line,
+                name: name.clone(),
+            },
+            ParseError::InvalidString(_, value, reason) => ErrorFields::InvalidString {
+                line,
+                text: value.clone(),
+                reason: *reason,
+            },
+            ParseError::InvalidStringCharacter(_, value, chr) => {
+                ErrorFields::InvalidStringCharacter {
+                    line,
+                    text: value.clone(),
+                    chr: *chr,
+                }
+            }
+            ParseError::UnmappedCharacter(_, value, chr) => ErrorFields::UnmappedCharacter {
+                line,
+                text: value.clone(),
+                chr: *chr,
+            },
+            ParseError::InvalidLabel(_, label, reason) => ErrorFields::InvalidLabel {
+                line,
+                label: label.clone(),
+                reason: *reason,
+            },
+            ParseError::UnknownSymbol(_, name) => ErrorFields::UnknownSymbol {
+                line,
+                name: name.clone(),
+            },
+            ParseError::ProgramTooBig(_) => ErrorFields::ProgramTooBig { line },
+            ParseError::DeniedWarning(_, warning, message) => ErrorFields::DeniedWarning {
+                line,
+                warning: *warning,
+                message: message.clone(),
+            },
+        }
+    }
+}
+
+impl From<ErrorFields> for ParseError {
+    fn from(fields: ErrorFields) -> Self {
+        match fields {
+            ErrorFields::EmptyLine => ParseError::EmptyLine,
+            ErrorFields::General { line, text, reason } => ParseError::General(line, text, reason),
+            ErrorFields::AddressHexFormat { line, text, error } => {
+                ParseError::AddressHexFormat(line, text, error)
+            }
+            ErrorFields::AddressNumFormat { line, text, error } => {
+                ParseError::AddressNumFormat(line, text, error)
+            }
+            ErrorFields::AddressTooBig { line, text } => ParseError::AddressTooBig(line, text),
+            ErrorFields::NumberFormat { line, text, error } => {
+                ParseError::NumberFormat(line, text, error)
+            }
+            ErrorFields::NumberHexFormat { line, text, error } => {
+                ParseError::NumberHexFormat(line, text, error)
+            }
+            ErrorFields::NumberTooBig { line, text } => ParseError::NumberTooBig(line, text),
+            ErrorFields::InvalidRegister { line, text, reason } => {
+                ParseError::InvalidRegister(line, text, reason)
+            }
+            ErrorFields::SignedNumberNumFormat { line, text, error } => {
+                ParseError::SignedNumberNumFormat(line, text, error)
+            }
+            ErrorFields::SignedNumberNumRange { line, text } => {
+                ParseError::SignedNumberNumRange(line, text)
+            }
+            ErrorFields::NumberMustBeByte { line, text } => {
+                ParseError::NumberMustBeByte(line, text)
+            }
+            ErrorFields::InvalidOpCode { line, op_code } => {
+                ParseError::InvalidOpCode(line, op_code)
+            }
+            ErrorFields::InvalidArguments {
+                line,
+                found,
+                op,
+                forms,
+            } => ParseError::InvalidArguments(line, found, op, forms),
+            ErrorFields::MissingArguments { line, op, forms } => {
+                ParseError::MissingArguments(line, op, forms)
+            }
+            ErrorFields::InvalidOpName { line, op } => ParseError::InvalidOpName(line, op),
+            ErrorFields::InvalidCharacter { line, text } => {
+                ParseError::InvalidCharacter(line, text)
+            }
+            ErrorFields::InvalidOffset { line, text } => ParseError::InvalidOffset(line, text),
+            ErrorFields::InvalidDirective {
+                line,
+                directive,
+                reason,
+            } => ParseError::InvalidDirective(line, directive, reason),
+            ErrorFields::UnterminatedBlock {
+                line,
+                block,
+                block_kind,
+            } => ParseError::UnterminatedBlock(line, block, block_kind),
+            ErrorFields::DuplicateSymbol { line, name } => ParseError::DuplicateSymbol(line, name),
+            ErrorFields::InvalidString { line, text, reason } => {
+                ParseError::InvalidString(line, text, reason)
+            }
+            ErrorFields::InvalidStringCharacter { line, text, chr } => {
+                ParseError::InvalidStringCharacter(line, text, chr)
+            }
+            ErrorFields::UnmappedCharacter { line, text, chr } => {
+                ParseError::UnmappedCharacter(line, text, chr)
+            }
+            ErrorFields::InvalidLabel {
+                line,
+                label,
+                reason,
+            } => ParseError::InvalidLabel(line, label, reason),
+            ErrorFields::UnknownSymbol { line, name } => ParseError::UnknownSymbol(line, name),
+            ErrorFields::ProgramTooBig { line } => ParseError::ProgramTooBig(line),
+            ErrorFields::DeniedWarning {
+                line,
+                warning,
+                message,
+            } => ParseError::DeniedWarning(line, warning, message),
+        }
+    }
+}
+
+impl Serialize for ParseError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ErrorFields::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ParseError {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ErrorFields::deserialize(deserializer).map(ParseError::from)
+    }
+}
@@ -0,0 +1,563 @@
+use crate::ArgKind;
+use std::fmt::{Display, Formatter};
+use ErrorCategory::*;
+use Severity::{Bug, Error};
+
+/// Broad group of an error, see `ParseError::category`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ErrorCategory {
+    /// Splitting lines, char and string literals
+    Lexing,
+    /// Number and address literals
+    Number,
+    /// Registers and indirect offsets
+    Register,
+    /// Op names and argument patterns
+    OpMatching,
+    /// `.struct`, `.db`, `.if_eq`, etc
+    Directive,
+    /// Labels and constants
+    Symbol,
+    /// The program as a whole
+    Program,
+    /// Warnings denied by `ParseOptions.warnings_as_errors`
+    Lint,
+}
+
+impl ErrorCategory {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorCategory::Lexing => "lexing",
+            ErrorCategory::Number => "number",
+            ErrorCategory::Register => "register",
+            ErrorCategory::OpMatching => "op matching",
+            ErrorCategory::Directive => "directive",
+            ErrorCategory::Symbol => "symbol",
+            ErrorCategory::Program => "program",
+            ErrorCategory::Lint => "lint",
+        }
+    }
+}
+
+impl Display for ErrorCategory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// How serious an error is, all of them stop the program from being assembled
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Severity {
+    /// The source is invalid
+    Error,
+    /// The source is valid but a warning was denied
+    Warning,
+    /// A bug in the parser, the source may be valid
+    Bug,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Bug => "bug",
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Why a line couldn't be parsed, see `ParseError::General`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum GeneralReason {
+    /// `$` without a number, e.g. `JMP $zz`
+    MissingAddress,
+    /// The line had no label or op (internal parser error)
+    NoContents,
+}
+
+impl Display for GeneralReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeneralReason::MissingAddress => write!(f, "No address after $"),
+            GeneralReason::NoContents => write!(f, "not empty but no contents?"),
+        }
+    }
+}
+
+/// Why a register is invalid, see `ParseError::InvalidRegister`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum RegisterReason {
+    /// Not a register name, e.g. `ex`, with the kinds of register allowed there
+    UnknownName(Vec<ArgKind>),
+    /// `(` without `)`, e.g. `(bx`
+    Unclosed,
+    /// A pre/post increment or decrement with an offset, e.g. `(bx)+ + 1`
+    PpidAndOffset,
+}
+
+impl Display for RegisterReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegisterReason::UnknownName(kinds) => {
+                let names: Vec<&str> = kinds.iter().map(ArgKind::name).collect();
+                write!(f, "{}", names.join(" or "))
+            }
+            RegisterReason::Unclosed => write!(f, "')' at end, as '(' was found at start"),
+            RegisterReason::PpidAndOffset => write!(f, "PPID or offset, not both"),
+        }
+    }
+}
+
+/// Why a directive is invalid, see `ParseError::InvalidDirective`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum DirectiveReason {
+    /// Not a data directive (internal parser error)
+    Unknown,
+    /// A data directive without values, e.g. `.db`
+    MissingValues,
+    /// A string in `.dw`
+    StringInWords,
+    /// A value that isn't a string in `.strz` or `.strl`
+    NotString(String),
+    /// A `.strl` string that is longer than 255 bytes
+    StringTooLong,
+    /// `.charmap` without a char (or string) and a value
+    CharmapFormat,
+    /// `\0` or `\x` in a `.charmap` string
+    UnmappableEscape,
+    /// `.charmap` with something other than a char or string, e.g. `.charmap A, 1`
+    NotCharOrString(String),
+    /// A field other than `.b` or `.w` in a struct, with the struct name
+    InvalidField(String),
+    /// A struct larger than 65535 bytes
+    StructTooBig,
+    /// An enum member after 65535
+    EnumTooBig,
+    /// A field name containing `.`
+    DotInField(String),
+    /// An enum member name containing `.`
+    DotInMember(String),
+    /// A value that isn't a number or constant
+    NotNumber(String),
+    /// A field count that isn't 0 - 65535
+    InvalidCount(String),
+    /// A name that doesn't start with a letter or _ or contains other characters
+    InvalidName(String),
+    /// A name that is a register, e.g. `.struct ax`
+    RegisterName(String),
+    /// A name starting with `__`, which is reserved for control directives
+    ReservedName(String),
+    /// `.ends`, `.ende`, `.else`, `.endif` or `.endl` without a block
+    NoBlockToEnd,
+    /// An unknown `.if_<cond>` or `.loop_until` condition
+    UnknownCondition(String),
+    /// `.loop_until` without a condition
+    MissingCondition,
+    /// `.else`, `.endif` or `.endl` with args
+    UnexpectedArgs,
+    /// A second `.else` in an if block
+    DuplicateElse,
+    /// A block end that doesn't match the open if block, with the line of the if
+    UnclosedIf(usize),
+    /// A block end that doesn't match the open loop, with the line of the loop
+    UnclosedLoop(usize),
+    /// The first compare arg isn't a register, so the size isn't known
+    CompareSize(String),
+    /// A condition with one or more than two compare args
+    CompareArgs,
+}
+
+impl Display for DirectiveReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DirectiveReason::Unknown => write!(f, "unknown directive"),
+            DirectiveReason::MissingValues => write!(f, "requires at least one value"),
+            DirectiveReason::StringInWords => write!(f, "strings must use .db, .strz or .strl"),
+            DirectiveReason::NotString(value) => write!(f, "'{value}' is not a string"),
+            DirectiveReason::StringTooLong => write!(f, "string must be 255 bytes or less"),
+            DirectiveReason::CharmapFormat => write!(
+                f,
+                "must be .charmap 'c', value or .charmap \"chars\", value"
+            ),
+            DirectiveReason::UnmappableEscape => write!(f, "\\0 and \\x can't be mapped"),
+            DirectiveReason::NotCharOrString(value) => {
+                write!(f, "'{value}' is not a char or string")
+            }
+            DirectiveReason::InvalidField(name) => {
+                write!(f, "only .b and .w fields are allowed in {name}")
+            }
+            DirectiveReason::StructTooBig => write!(f, "struct is larger than 65535 bytes"),
+            DirectiveReason::EnumTooBig => write!(f, "enum value would be larger than 65535"),
+            DirectiveReason::DotInField(name) => {
+                write!(f, "'{name}' field names can't contain '.'")
+            }
+            DirectiveReason::DotInMember(name) => {
+                write!(f, "'{name}' member names can't contain '.'")
+            }
+            DirectiveReason::NotNumber(value) => write!(f, "'{value}' is not a number"),
+            DirectiveReason::InvalidCount(count) => {
+                write!(f, "'{count}' is not a valid count, must be 0 - 65535")
+            }
+            DirectiveReason::InvalidName(name) => write!(f, "'{name}' is not a valid name"),
+            DirectiveReason::RegisterName(name) => write!(f, "'{name}' is a register"),
+            DirectiveReason::ReservedName(name) => write!(
+                f,
+                "'{name}' starts with __ which is reserved for control directives"
+            ),
+            DirectiveReason::NoBlockToEnd => write!(f, "no block to end"),
+            DirectiveReason::UnknownCondition(name) => write!(
+                f,
+                "unknown condition '{name}', expected eq, ne, lt, ge, gt or le"
+            ),
+            DirectiveReason::MissingCondition => write!(f, "missing condition"),
+            DirectiveReason::UnexpectedArgs => write!(f, "doesn't take any args"),
+            DirectiveReason::DuplicateElse => write!(f, "if block already has .else"),
+            DirectiveReason::UnclosedIf(line_num) => write!(
+                f,
+                "expected .else or .endif to close if on line {line_num}"
+            ),
+            DirectiveReason::UnclosedLoop(line_num) => {
+                write!(f, "expected .endl to close loop on line {line_num}")
+            }
+            DirectiveReason::CompareSize(arg) => write!(
+                f,
+                "{arg} must be a register so the compare size is known, use CMP.B or CMP.W before the directive instead"
+            ),
+            DirectiveReason::CompareArgs => write!(
+                f,
+                "expected a condition and optionally two args to compare"
+            ),
+        }
+    }
+}
+
+/// Type of a block that was never closed, see `ParseError::UnterminatedBlock`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum BlockKind {
+    /// `.struct`, closed by `.ends`
+    Struct,
+    /// `.enum`, closed by `.ende`
+    Enum,
+    /// `.if_<cond>`, closed by `.endif`
+    If,
+    /// `.loop_until`, closed by `.endl`
+    Loop,
+}
+
+impl BlockKind {
+    /// The directive that closes the block, e.g. `.ends`
+    pub fn end_directive(&self) -> &'static str {
+        match self {
+            BlockKind::Struct => ".ends",
+            BlockKind::Enum => ".ende",
+            BlockKind::If => ".endif",
+            BlockKind::Loop => ".endl",
+        }
+    }
+}
+
+/// Why a string literal is invalid, see `ParseError::InvalidString`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum StringReason {
+    /// Doesn't start and end with `"`
+    Unquoted,
+    /// `\x` without two hex digits, e.g. `"\x1"`
+    HexEscape,
+    /// An escape other than `\n`, `\r`, `\t`, `\0`, `\\`, `\'`, `\"` or `\x`
+    UnknownEscape(char),
+    /// Ends with a single `\`
+    TrailingBackslash,
+    /// A `"` inside the string that isn't escaped
+    UnescapedQuote,
+}
+
+impl Display for StringReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StringReason::Unquoted => write!(f, "must start and end with \""),
+            StringReason::HexEscape => write!(f, "\\x must be followed by two hex digits"),
+            StringReason::UnknownEscape(chr) => write!(f, "unknown escape \\{chr}"),
+            StringReason::TrailingBackslash => write!(f, "ends with \\"),
+            StringReason::UnescapedQuote => write!(f, "\" must be escaped"),
+        }
+    }
+}
+
+/// Why a label is invalid, see `ParseError::InvalidLabel`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum LabelReason {
+    /// Doesn't start with a letter or _ or contains other characters, e.g. `1st:`
+    InvalidName,
+    /// A register name, e.g. `ax:`
+    Register,
+    /// Starts with `__`, which is reserved for control directives
+    Reserved,
+    /// A local label before any global label
+    NoScope,
+}
+
+impl Display for LabelReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LabelReason::InvalidName => write!(
+                f,
+                "must start with a letter or _ and only contain letters, numbers and _"
+            ),
+            LabelReason::Register => write!(f, "is a register"),
+            LabelReason::Reserved => write!(
+                f,
+                "names starting with __ are reserved for control directives"
+            ),
+            LabelReason::NoScope => write!(f, "local labels must be after a global label"),
+        }
+    }
+}
+
+/// Documentation for an error code, see `explain`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ErrorInfo {
+    /// e.g. `E0013`
+    pub code: &'static str,
+    /// `ParseError` variant
+    pub name: &'static str,
+    pub category: ErrorCategory,
+    pub severity: Severity,
+    pub explanation: &'static str,
+}
+
+const fn info(
+    code: &'static str,
+    name: &'static str,
+    category: ErrorCategory,
+    severity: Severity,
+    explanation: &'static str,
+) -> ErrorInfo {
+    ErrorInfo {
+        code,
+        name,
+        category,
+        severity,
+        explanation,
+    }
+}
+
+/// Every error code, in order, codes are never reused or renumbered
+pub const ERRORS: [ErrorInfo; 28] = [
+    info("E0000", "EmptyLine", Lexing, Bug,
+        "An empty line was passed to the line parser. Empty lines and comments are skipped before this so this is a bug in the parser."),
+    info("E0001", "General", Lexing, Error,
+        "The line couldn't be split into a label, op and arguments, or an address has no number after the `$`, e.g. `JMP $zz`."),
+    info("E0002", "AddressHexFormat", Number, Error,
+        "A hex address isn't valid hex, e.g. `$xZZ`. Hex addresses are `$x0` to `$xFFFF`."),
+    info("E0003", "AddressNumFormat", Number, Error,
+        "A decimal address isn't a valid number, e.g. `$1a`. Decimal addresses are `$0` to `$65535`."),
+    info("E0004", "AddressTooBig", Number, Error,
+        "An address is larger than 65535 (`$xFFFF`), e.g. `$70000`."),
+    info("E0005", "NumberFormat", Number, Error,
+        "A number isn't valid, e.g. `12a`. Numbers are decimal (`12`), hex (`xC`), binary (`b1100`) or a char literal (`'a'`)."),
+    info("E0006", "NumberHexFormat", Number, Error,
+        "A hex number isn't valid hex, e.g. `xZZ`. Hex numbers are `x0` to `xFFFF`."),
+    info("E0007", "NumberTooBig", Number, Error,
+        "A number is larger than 65535 (`xFFFF`), e.g. `70000`."),
+    info("E0008", "InvalidRegister", Register, Error,
+        "A register name or format isn't valid, e.g. `ex` or `(bx`. Registers are `ah`-`dl` (bytes), `ax`-`dx` (words) and `flg`, indirect registers are written as `(bx)`."),
+    info("E0009", "SignedNumberNumFormat", Number, Error,
+        "A negative number isn't valid, e.g. `-1a`."),
+    info("E0010", "SignedNumberNumRange", Number, Error,
+        "A negative number is outside -32768 to 32767, e.g. `-40000`."),
+    info("E0011", "NumberMustBeByte", Number, Error,
+        "A constant or data value is larger than 255 where a byte is needed, e.g. `ADD.B al, State.dead` when `State.dead` is 256 or `.db 300`. Use the word version of the op (`.W`) or a smaller number."),
+    info("E0012", "InvalidOpCode", OpMatching, Error,
+        "An op code doesn't exist. This isn't produced by the parser and is reserved for tools that read bytes."),
+    info("E0013", "InvalidArguments", OpMatching, Error,
+        "The arguments don't match any form of the op, e.g. `ADD.B ax, 1` as `ax` is a word register. The error lists the supported forms."),
+    info("E0014", "MissingArguments", OpMatching, Error,
+        "An op that requires arguments has none, e.g. `INC.B`. The error lists the supported forms."),
+    info("E0015", "InvalidOpName", OpMatching, Error,
+        "No op or pseudo-op has this name. Most ops need a size, e.g. `ADD.B` or `ADD.W` rather than `ADD`."),
+    info("E0016", "InvalidCharacter", Lexing, Error,
+        "A char literal isn't one ASCII character in single quotes, e.g. `'ab'` or `'é'`."),
+    info("E0017", "InvalidOffset", Register, Error,
+        "The offset of an indirect register isn't a register or number, e.g. `(bx + ?)`. Offsets are `(bx + al)`, `(bx + cx)` or `(bx + 12)`."),
+    info("E0018", "InvalidDirective", Directive, Error,
+        "A directive is unknown, has invalid arguments or is in the wrong place, e.g. `.ends` without `.struct` or `.else` outside an `.if_<cond>` block."),
+    info("E0019", "UnterminatedBlock", Directive, Error,
+        "A block was never closed, e.g. `.struct` without `.ends`, `.enum` without `.ende`, `.if_<cond>` without `.endif` or `.loop_until` without `.endl`."),
+    info("E0020", "DuplicateSymbol", Symbol, Error,
+        "A label or constant has the same name as an earlier label or constant."),
+    info("E0021", "InvalidString", Lexing, Error,
+        "A string literal isn't closed or has an invalid escape, e.g. `\"abc` or `\"\\q\"`."),
+    info("E0022", "InvalidStringCharacter", Lexing, Error,
+        "A string literal contains a non ASCII character, use `\\x` escapes or a charmap instead."),
+    info("E0023", "UnmappedCharacter", Lexing, Error,
+        "A charmap is set but a char or string uses a character that isn't in it. Add it with `.charmap`."),
+    info("E0024", "InvalidLabel", Symbol, Error,
+        "A label name isn't valid, e.g. `1st:` or `ax:`. Labels start with a letter or `_`, can't be registers and local labels (`.loop:`) must come after a global label."),
+    info("E0025", "UnknownSymbol", Symbol, Error,
+        "An argument refers to a label or constant that doesn't exist, e.g. `JMP mian`."),
+    info("E0026", "ProgramTooBig", Program, Error,
+        "The program doesn't fit in 65536 bytes (the size of the address space)."),
+    info("E0027", "DeniedWarning", Lint, Severity::Warning,
+        "A warning was found and `ParseOptions.warnings_as_errors` is set. Fix the warning or allow it with `# lint: allow(name)`."),
+];
+
+/// Returns the documentation for a code (e.g. `E0013`)
+pub fn error_info(code: &str) -> Option<&'static ErrorInfo> {
+    ERRORS
+        .iter()
+        .find(|info| info.code.eq_ignore_ascii_case(code))
+}
+
+/// Returns a description of an error code (e.g. `E0013`), like `rustc --explain`
+pub fn explain(code: &str) -> Option<String> {
+    error_info(code).map(|info| {
+        format!(
+            "{} {} ({}, {})\n\n{}",
+            info.code, info.name, info.category, info.severity, info.explanation
+        )
+    })
+}
+
+/// Generates `docs/errors.md`
+pub fn error_reference() -> String {
+    let mut output = String::from("# Error codes\n\n");
+    output.push_str("Generated from the parser, do not edit\n\n");
+    output
+        .push_str("| Code | Error | Category | Severity | Explanation |\n|---|---|---|---|---|\n");
+    for info in ERRORS {
+        output.push_str(&format!(
+            "| {} | {} | {} | {} | {} |\n",
+            info.code,
+            info.name,
+            info.category,
+            info.severity,
+            info.explanation.replace('|', "\\|")
+        ));
+    }
+    output
+}
+
+/// `ParseIntError` can't be created directly, so it's serialized as its `IntErrorKind` (e.g.
+/// `"invalid_digit"`) and deserialized by parsing a number that fails with the same kind
+#[cfg(feature = "serde")]
+pub(crate) mod int_error_serde {
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::num::{IntErrorKind, NonZeroU8, ParseIntError};
+
+    pub fn serialize<S: Serializer>(err: &ParseIntError, serializer: S) -> Result<S::Ok, S::Error> {
+        let name = match err.kind() {
+            IntErrorKind::Empty => "empty",
+            IntErrorKind::InvalidDigit => "invalid_digit",
+            IntErrorKind::PosOverflow => "pos_overflow",
+            IntErrorKind::NegOverflow => "neg_overflow",
+            IntErrorKind::Zero => "zero",
+            kind => return Err(S::Error::custom(format!("unsupported int error {kind:?}"))),
+        };
+        serializer.serialize_str(name)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ParseIntError, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        let result = match name.as_str() {
+            "empty" => "".parse::<u8>(),
+            "invalid_digit" => "a".parse::<u8>(),
+            "pos_overflow" => "256".parse::<u8>(),
+            "neg_overflow" => "-129".parse::<i8>().map(|num| num as u8),
+            "zero" => "0".parse::<NonZeroU8>().map(NonZeroU8::get),
+            _ => return Err(D::Error::custom(format!("unknown int error {name}"))),
+        };
+        result
+            .err()
+            .ok_or_else(|| D::Error::custom(format!("unknown int error {name}")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_codes() {
+        for (idx, info) in ERRORS.iter().enumerate() {
+            assert_eq!(info.code, format!("E{idx:04}"));
+        }
+        assert_eq!(
+            error_info("e0013").map(|info| info.name),
+            Some("InvalidArguments")
+        );
+        assert!(error_info("E9999").is_none());
+    }
+
+    #[test]
+    fn test_explain() {
+        let text = explain("E0015").unwrap();
+        assert!(text.starts_with("E0015 InvalidOpName (op matching, error)\n\n"));
+        assert!(explain("missing").is_none());
+    }
+
+    #[test]
+    fn test_error_reference_is_current() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("docs/errors.md");
+        let generated = error_reference();
+        if std::env::var_os("UPDATE_REFERENCE").is_some() {
+            fs::write(&path, &generated).unwrap();
+        }
+        let existing = fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            existing == generated,
+            "docs/errors.md is out of date, run `UPDATE_REFERENCE=1 cargo test reference`"
+        );
+    }
+}
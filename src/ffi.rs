@@ -0,0 +1,244 @@
+//! C API, see `include/maikor_asm_parser.h`
+//!
+//! Results are owned by the library and must be freed with `maikor_asm_result_free`
+
+use crate::interpreter::arg_spans;
+use crate::lexer::tokenize;
+use crate::warnings::{arg_span, code_span};
+use crate::{parse_program, ParseError};
+use std::ffi::{c_char, CString};
+use std::ptr;
+
+/// An error from `maikor_asm_assemble`
+#[repr(C)]
+pub struct MaikorAsmError {
+    /// Zero based line number, `SIZE_MAX` if the error isn't on a line
+    pub line: usize,
+    /// Zero based byte offset of the argument or token the error is about (or the start of the
+    /// code if it isn't known), `SIZE_MAX` if the error isn't on a line
+    pub column: usize,
+    /// Stable error code, e.g. `E0013`
+    pub code: *mut c_char,
+    pub message: *mut c_char,
+}
+
+/// Output of `maikor_asm_assemble`, `bytes` is NULL if there are any errors
+#[repr(C)]
+pub struct MaikorAsmResult {
+    pub bytes: *mut u8,
+    pub bytes_len: usize,
+    pub errors: *mut MaikorAsmError,
+    pub errors_len: usize,
+}
+
+/// Assembles `len` bytes of UTF-8 source (which doesn't need to be NUL terminated)
+///
+/// Returns NULL if `source` is NULL and `len` isn't 0, otherwise the result must be freed with
+/// `maikor_asm_result_free`
+///
+/// # Safety
+///
+/// `source` must point to at least `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn maikor_asm_assemble(
+    source: *const u8,
+    len: usize,
+) -> *mut MaikorAsmResult {
+    let source = match (source.is_null(), len) {
+        (_, 0) => &[][..],
+        (true, _) => return ptr::null_mut(),
+        (false, _) => std::slice::from_raw_parts(source, len),
+    };
+    let source = String::from_utf8_lossy(source);
+    let lines: Vec<&str> = source.lines().collect();
+    let (bytes, errors) = match parse_program(&lines) {
+        Ok(program) => (program.bytes, vec![]),
+        Err(err) => (vec![], vec![error(&lines, &err)]),
+    };
+    let (bytes, bytes_len) = into_raw(bytes);
+    let (errors, errors_len) = into_raw(errors);
+    Box::into_raw(Box::new(MaikorAsmResult {
+        bytes,
+        bytes_len,
+        errors,
+        errors_len,
+    }))
+}
+
+/// Frees a result from `maikor_asm_assemble`, including the bytes and errors
+///
+/// # Safety
+///
+/// `result` must be NULL or from `maikor_asm_assemble` and not already freed
+#[no_mangle]
+pub unsafe extern "C" fn maikor_asm_result_free(result: *mut MaikorAsmResult) {
+    if result.is_null() {
+        return;
+    }
+    let result = Box::from_raw(result);
+    drop(from_raw(result.bytes, result.bytes_len));
+    for error in from_raw(result.errors, result.errors_len).iter() {
+        drop(CString::from_raw(error.code));
+        drop(CString::from_raw(error.message));
+    }
+}
+
+fn error(lines: &[&str], err: &ParseError) -> MaikorAsmError {
+    let (line, column) = match err.line_num() {
+        Some(line) => (line, column(lines, line, err)),
+        None => (usize::MAX, usize::MAX),
+    };
+    MaikorAsmError {
+        line,
+        column,
+        code: c_string(err.code()),
+        message: c_string(&err.to_string()),
+    }
+}
+
+/// Start of the arg or token matching the error's text, the first arg for `InvalidArguments`
+/// or the start of the code
+fn column(lines: &[&str], line_num: usize, err: &ParseError) -> usize {
+    let line = lines.get(line_num).copied().unwrap_or_default();
+    let code = code_span(lines, line_num);
+    let text = match err {
+        ParseError::InvalidArguments(..) => return arg_span(lines, line_num, 0).start,
+        _ => match error_text(err) {
+            Some(text) if !text.is_empty() => text,
+            _ => return code.start,
+        },
+    };
+    arg_spans(line)
+        .into_iter()
+        .chain(tokenize(line).into_iter().map(|token| token.span))
+        .find(|span| line.get(span.clone()) == Some(text))
+        .map(|span| span.start)
+        .or_else(|| line[code.clone()].find(text).map(|idx| code.start + idx))
+        .unwrap_or(code.start)
+}
+
+/// Source text (an arg, label, etc) in the error, if any
+fn error_text(err: &ParseError) -> Option<&str> {
+    match err {
+        ParseError::General(_, text, _)
+        | ParseError::AddressHexFormat(_, text, _)
+        | ParseError::AddressNumFormat(_, text, _)
+        | ParseError::AddressTooBig(_, text)
+        | ParseError::NumberFormat(_, text, _)
+        | ParseError::NumberHexFormat(_, text, _)
+        | ParseError::NumberTooBig(_, text)
+        | ParseError::InvalidRegister(_, text, _)
+        | ParseError::SignedNumberNumFormat(_, text, _)
+        | ParseError::SignedNumberNumRange(_, text)
+        | ParseError::NumberMustBeByte(_, text)
+        | ParseError::InvalidOpName(_, text)
+        | ParseError::InvalidCharacter(_, text)
+        | ParseError::InvalidOffset(_, text)
+        | ParseError::DuplicateSymbol(_, text)
+        | ParseError::InvalidString(_, text, _)
+        | ParseError::InvalidStringCharacter(_, text, _)
+        | ParseError::UnmappedCharacter(_, text, _)
+        | ParseError::InvalidLabel(_, text, _)
+        | ParseError::UnknownSymbol(_, text) => Some(text),
+        _ => None,
+    }
+}
+
+/// NULs are escaped as the source text is included in messages
+fn c_string(text: &str) -> *mut c_char {
+    CString::new(text.replace('\0', "\\0"))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+/// Empty lists are NULL
+fn into_raw<T>(list: Vec<T>) -> (*mut T, usize) {
+    if list.is_empty() {
+        return (ptr::null_mut(), 0);
+    }
+    let len = list.len();
+    (Box::into_raw(list.into_boxed_slice()) as *mut T, len)
+}
+
+/// # Safety
+///
+/// `list` must be NULL or from `into_raw` with the same `len`
+unsafe fn from_raw<T>(list: *mut T, len: usize) -> Box<[T]> {
+    if list.is_null() {
+        Box::default()
+    } else {
+        Box::from_raw(ptr::slice_from_raw_parts_mut(list, len))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_assemble() {
+        let source = "main: nop\n  jmp main";
+        unsafe {
+            let result = maikor_asm_assemble(source.as_ptr(), source.len());
+            let result_ref = &*result;
+            assert_eq!(result_ref.errors_len, 0);
+            assert!(result_ref.errors.is_null());
+            assert_eq!(
+                std::slice::from_raw_parts(result_ref.bytes, result_ref.bytes_len),
+                &[0, 176, 0, 0]
+            );
+            maikor_asm_result_free(result);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let source = "nop\n  add.b ax, 1";
+        unsafe {
+            let result = maikor_asm_assemble(source.as_ptr(), source.len());
+            let result_ref = &*result;
+            assert!(result_ref.bytes.is_null());
+            assert_eq!(result_ref.errors_len, 1);
+            let error = &*result_ref.errors;
+            assert_eq!((error.line, error.column), (1, 8));
+            assert_eq!(CStr::from_ptr(error.code).to_str(), Ok("E0013"));
+            assert!(CStr::from_ptr(error.message)
+                .to_string_lossy()
+                .contains("ADD.B"));
+            maikor_asm_result_free(result);
+
+            assert!(maikor_asm_assemble(ptr::null(), 1).is_null());
+            let empty = maikor_asm_assemble(ptr::null(), 0);
+            assert_eq!((*empty).bytes_len, 0);
+            maikor_asm_result_free(empty);
+            maikor_asm_result_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_error_column() {
+        let column = |lines: &[&str]| {
+            let err = parse_program(lines).unwrap_err();
+            let error = error(lines, &err);
+            unsafe {
+                drop(CString::from_raw(error.code));
+                drop(CString::from_raw(error.message));
+            }
+            (error.line, error.column)
+        };
+        assert_eq!(column(&["nop", "  jmp missing"]), (1, 6));
+        assert_eq!(column(&["main: cpy.b al, x1FFFF"]), (0, 16));
+        assert_eq!(column(&["cpy.b (bx + zz), 1"]), (0, 12));
+        assert_eq!(column(&["  ad.b al, 1"]), (0, 2));
+        assert_eq!(column(&["main: nop", "main: nop"]), (1, 0));
+        assert_eq!(column(&[".if_eq", "nop"]), (0, 0));
+
+        let error = error(&[], &ParseError::EmptyLine);
+        assert_eq!((error.line, error.column), (usize::MAX, usize::MAX));
+        unsafe {
+            drop(CString::from_raw(error.code));
+            drop(CString::from_raw(error.message));
+        }
+    }
+}
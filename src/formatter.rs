@@ -0,0 +1,239 @@
+use crate::directives::{ENUM, ENUM_END};
+use crate::interpreter::{interpret_line, split_args, split_comment};
+use crate::lexer::split_at_punct;
+use maikor_platform::registers::id;
+
+/// Minimum width of the label column, so ops are always indented
+const MIN_LABEL_WIDTH: usize = 4;
+
+#[derive(Debug, Default)]
+struct FormattedLine {
+    label: Option<String>,
+    op: Option<String>,
+    args: String,
+    comment: Option<String>,
+}
+
+/// Formats Maikor ASM source
+///
+/// - Ops are uppercase (`ADD.B`), directives and registers are lowercase
+/// - Labels, ops, args and comments are aligned in columns
+/// - Args are separated by `, ` and registers are written as `-(bx)`, `(bx + al)`, etc
+///
+/// Comments and blank lines are kept, and formatting formatted source doesn't change it
+pub fn format(source: &str) -> String {
+    let mut in_enum = false;
+    let lines: Vec<FormattedLine> = source
+        .lines()
+        .map(|line| {
+            let (code, comment) = split_comment(line);
+            let mut formatted = FormattedLine {
+                comment: comment.map(|text| text.to_string()),
+                ..FormattedLine::default()
+            };
+            if code.is_empty() {
+                return formatted;
+            }
+            if in_enum && !code.starts_with('.') {
+                //enum members are `name` or `name = value`
+                formatted.op = Some(split_args(code).join(", "));
+                return formatted;
+            }
+            if let Ok(line) = interpret_line(0, code) {
+                formatted.label = line.label;
+                if let Some((op, args)) = line.command {
+                    let op = format_op(&op);
+                    if op == ENUM.to_ascii_lowercase() {
+                        in_enum = true;
+                    } else if op == ENUM_END.to_ascii_lowercase() {
+                        in_enum = false;
+                    }
+                    formatted.args = args
+                        .iter()
+                        .map(|arg| format_arg(arg))
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    formatted.op = Some(op);
+                }
+            }
+            formatted
+        })
+        .collect();
+
+    let label_width = lines
+        .iter()
+        .filter(|line| line.op.is_some())
+        .filter_map(|line| line.label.as_ref())
+        .map(|label| label.chars().count() + 2)
+        .max()
+        .unwrap_or_default()
+        .max(MIN_LABEL_WIDTH);
+    let op_width = lines
+        .iter()
+        .filter(|line| !line.args.is_empty())
+        .filter_map(|line| line.op.as_ref())
+        .map(|op| op.chars().count() + 1)
+        .max()
+        .unwrap_or_default();
+    let code: Vec<String> = lines
+        .iter()
+        .map(|line| {
+            let label = line
+                .label
+                .as_ref()
+                .map(|label| format!("{label}:"))
+                .unwrap_or_default();
+            match &line.op {
+                Some(op) => {
+                    let code = format!("{label:label_width$}{op:op_width$}{}", line.args);
+                    code.trim_end().to_string()
+                }
+                None => label,
+            }
+        })
+        .collect();
+    let comment_column = lines
+        .iter()
+        .zip(&code)
+        .filter(|(line, code)| line.comment.is_some() && !code.is_empty())
+        .map(|(_, code)| code.chars().count() + 1)
+        .max()
+        .unwrap_or_default();
+
+    let mut output = lines
+        .iter()
+        .zip(code)
+        .map(|(line, code)| match &line.comment {
+            Some(comment) if code.is_empty() => comment.clone(),
+            Some(comment) => format!("{code:comment_column$}{comment}"),
+            None => code,
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    if source.ends_with('\n') {
+        output.push('\n');
+    }
+    output
+}
+
+/// Ops are uppercase and directives are lowercase
+fn format_op(op: &str) -> String {
+    if op.starts_with('.') {
+        op.to_ascii_lowercase()
+    } else {
+        op.to_ascii_uppercase()
+    }
+}
+
+/// Registers are converted to the canonical format, anything else is returned as is
+fn format_arg(arg: &str) -> String {
+    format_register(arg).unwrap_or_else(|| arg.to_string())
+}
+
+fn format_register(arg: &str) -> Option<String> {
+    let (pre, remaining, post) = if let Some(remaining) = arg.strip_prefix(['-', '+']) {
+        (&arg[..1], remaining, "")
+    } else if let Some(remaining) = arg.strip_suffix(['-', '+']) {
+        ("", remaining, &arg[arg.len() - 1..])
+    } else {
+        ("", arg, "")
+    };
+    let remaining = remaining.trim();
+    let (is_indirect, remaining) = match remaining
+        .strip_prefix('(')
+        .and_then(|text| text.strip_suffix(')'))
+    {
+        Some(inner) => (true, inner.trim()),
+        None => (false, remaining),
+    };
+    let (reg, offset) = match split_at_punct(remaining, '+') {
+        Some((reg, offset)) => (reg.trim(), Some(offset.trim())),
+        None => (remaining, None),
+    };
+    let reg = format_register_name(reg)?;
+    let offset = offset
+        .map(|offset| {
+            let offset = format_register_name(offset).unwrap_or_else(|| offset.to_string());
+            format!(" + {offset}")
+        })
+        .unwrap_or_default();
+    if is_indirect {
+        Some(format!("{pre}({reg}{offset}){post}"))
+    } else {
+        Some(format!("{pre}{reg}{offset}{post}"))
+    }
+}
+
+fn format_register_name(name: &str) -> Option<String> {
+    id::from_name(&name.to_ascii_uppercase()).ok()?;
+    Some(name.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_program;
+
+    #[test]
+    fn test_format() {
+        let source = r#"# test program
+.enum State
+  idle
+    walk   =   5
+.ende
+main:  cpy.b al,'A'  # load
+ .loop: inc.b   ah
+  jmp .loop # again
+  mcpy $255,- (    bx ) ,  1
+  add.w  (   bx +   al    )     ,  124
+.db   "a,  b" , 1
+@@:
+  Halt
+"#;
+        let expected = r#"# test program
+       .enum State
+       idle
+       walk = 5
+       .ende
+main:  CPY.B al, 'A' # load
+.loop: INC.B ah
+       JMP   .loop   # again
+       MCPY  $255, -(bx), 1
+       ADD.W (bx + al), 124
+       .db   "a,  b", 1
+@@:
+       HALT
+"#;
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+        let source: Vec<&str> = source.lines().collect();
+        let expected: Vec<&str> = expected.lines().collect();
+        assert_eq!(
+            parse_program(&source).unwrap().bytes,
+            parse_program(&expected).unwrap().bytes
+        );
+    }
+
+    #[test]
+    fn test_format_args() {
+        assert_eq!(format_arg("AL"), "al");
+        assert_eq!(format_arg("( BX )"), "(bx)");
+        assert_eq!(format_arg("(bx+al)"), "(bx + al)");
+        assert_eq!(format_arg("( bx + Enemy.hp )"), "(bx + Enemy.hp)");
+        assert_eq!(format_arg("(bx) +"), "(bx)+");
+        assert_eq!(format_arg("- ax"), "-ax");
+        assert_eq!(format_arg("-1"), "-1");
+        assert_eq!(format_arg("main"), "main");
+        assert_eq!(format_arg("' '"), "' '");
+    }
+
+    #[test]
+    fn test_format_preserves_comments() {
+        assert_eq!(format("# a\n\n  # b  "), "# a\n\n# b");
+        assert_eq!(
+            format("nop #x\ncpy.b al, ah # y"),
+            "    NOP          #x\n    CPY.B al, ah # y"
+        );
+        assert_eq!(format(""), "");
+    }
+}
@@ -0,0 +1,200 @@
+use crate::arg_patterns::ARG_MATCHES;
+use std::fmt::{Display, Formatter};
+
+/// Kind of argument accepted by an op, see [`OpForm`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ArgKind {
+    /// `$1234` or a label
+    Address,
+    /// `al`, `ah`, etc
+    Register,
+    /// `ax`, `bx`, etc
+    ExtReg,
+    /// `(bx)`, `(bx + al)`, `(bx + 12)`, etc
+    IndirectReg,
+    Word,
+    Byte,
+}
+
+impl ArgKind {
+    /// Converts a letter from an `ARG_MATCHES` pattern
+    pub fn from_letter(letter: char) -> Option<ArgKind> {
+        match letter {
+            'A' => Some(ArgKind::Address),
+            'R' => Some(ArgKind::Register),
+            'E' => Some(ArgKind::ExtReg),
+            'I' => Some(ArgKind::IndirectReg),
+            'W' => Some(ArgKind::Word),
+            'B' => Some(ArgKind::Byte),
+            _ => None,
+        }
+    }
+
+    pub fn letter(&self) -> char {
+        match self {
+            ArgKind::Address => 'A',
+            ArgKind::Register => 'R',
+            ArgKind::ExtReg => 'E',
+            ArgKind::IndirectReg => 'I',
+            ArgKind::Word => 'W',
+            ArgKind::Byte => 'B',
+        }
+    }
+
+    /// Short description, e.g. `reg8` or `imm16`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArgKind::Address => "addr",
+            ArgKind::Register => "reg8",
+            ArgKind::ExtReg => "reg16",
+            ArgKind::IndirectReg => "(reg16)",
+            ArgKind::Word => "imm16",
+            ArgKind::Byte => "imm8",
+        }
+    }
+
+    /// Encoded size in bytes, not including offsets for indirect registers
+    pub fn size(&self) -> usize {
+        match self {
+            ArgKind::Address | ArgKind::Word => 2,
+            ArgKind::Register | ArgKind::ExtReg | ArgKind::IndirectReg | ArgKind::Byte => 1,
+        }
+    }
+}
+
+impl Display for ArgKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// One set of arguments accepted by an op, e.g. `ADD.B reg8, imm8`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct OpForm {
+    pub op_name: &'static str,
+    pub args: Vec<ArgKind>,
+    pub op_code: u8,
+}
+
+impl OpForm {
+    /// Pattern letters as used in `ARG_MATCHES`, e.g. `RB`
+    pub fn pattern(&self) -> String {
+        self.args.iter().map(ArgKind::letter).collect()
+    }
+
+    /// Encoded size in bytes without any indirect offsets
+    pub fn min_size(&self) -> usize {
+        1 + self.args.iter().map(ArgKind::size).sum::<usize>()
+    }
+
+    /// Encoded size in bytes if every indirect register uses a number offset (2 bytes)
+    pub fn max_size(&self) -> usize {
+        let offsets = self
+            .args
+            .iter()
+            .filter(|arg| **arg == ArgKind::IndirectReg)
+            .count();
+        self.min_size() + offsets * 2
+    }
+}
+
+impl Display for OpForm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.op_name)?;
+        if !self.args.is_empty() {
+            write!(f, " {}", describe_args(&self.args))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns all forms for an op name (e.g. `ADD.B`), ordered by op code
+///
+/// Several forms may share an op code, e.g. `ADD.B reg8, reg8` and `ADD.B (reg16), reg8`
+pub fn op_forms(op_name: &str) -> Option<Vec<OpForm>> {
+    let (op_name, patterns) = ARG_MATCHES.get_key_value(op_name)?;
+    let mut forms: Vec<OpForm> = patterns
+        .iter()
+        .map(|(pattern, op_code)| OpForm {
+            op_name,
+            args: pattern_to_args(pattern),
+            op_code: *op_code,
+        })
+        .collect();
+    forms.sort_by_key(|form| (form.op_code, form.pattern()));
+    Some(forms)
+}
+
+/// Converts pattern letters (e.g. `RB`) to kinds, unknown letters are ignored
+pub fn pattern_to_args(pattern: &str) -> Vec<ArgKind> {
+    pattern.chars().filter_map(ArgKind::from_letter).collect()
+}
+
+/// Semicolon separated forms of an op, e.g. `INC.B reg8; INC.B (reg16); INC.B addr`
+pub fn describe_forms(op_name: &str, forms: &[Vec<ArgKind>]) -> String {
+    forms
+        .iter()
+        .map(|args| {
+            if args.is_empty() {
+                op_name.to_string()
+            } else {
+                format!("{op_name} {}", describe_args(args))
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// Comma separated argument descriptions, e.g. `reg8, imm8`
+pub fn describe_args(args: &[ArgKind]) -> String {
+    args.iter()
+        .map(ArgKind::name)
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maikor_platform::ops;
+
+    #[test]
+    fn test_op_forms() {
+        let forms = op_forms("ADD.B").unwrap();
+        let find = |pattern: &str| forms.iter().find(|form| form.pattern() == pattern).unwrap();
+        let add_reg_num = find("RB");
+        assert_eq!(add_reg_num.op_code, ops::ADD_REG_NUM_BYTE);
+        assert_eq!(add_reg_num.to_string(), "ADD.B reg8, imm8");
+        assert_eq!(add_reg_num.min_size(), 3);
+        assert_eq!(add_reg_num.max_size(), 3);
+        let add_addr_ind = find("AI");
+        assert_eq!(add_addr_ind.op_code, ops::ADD_ADDR_REG_BYTE);
+        assert_eq!(add_addr_ind.to_string(), "ADD.B addr, (reg16)");
+        assert_eq!(add_addr_ind.min_size(), 4);
+        assert_eq!(add_addr_ind.max_size(), 6);
+        assert!(forms
+            .windows(2)
+            .all(|pair| pair[0].op_code <= pair[1].op_code));
+
+        let nop = op_forms("NOP").unwrap();
+        assert_eq!(nop.len(), 1);
+        assert_eq!(nop[0].to_string(), "NOP");
+        assert_eq!(nop[0].min_size(), 1);
+
+        assert!(op_forms("ADD").is_none());
+    }
+
+    #[test]
+    fn test_all_forms() {
+        for op_name in ARG_MATCHES.keys() {
+            for form in op_forms(op_name).unwrap() {
+                assert_eq!(
+                    ARG_MATCHES[op_name][form.pattern().as_str()],
+                    form.op_code,
+                    "{form}"
+                );
+            }
+        }
+    }
+}
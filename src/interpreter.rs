@@ -1,40 +1,34 @@
+use crate::lexer::{tokenize, tokens_text, Token, TokenKind};
 use crate::ParseError::{EmptyLine, General};
-use crate::{Line, ParseError};
+use crate::{GeneralReason, Line, ParseError};
+use std::ops::Range;
 
 pub fn interpret_line(line_num: usize, trimmed: &str) -> Result<Line, ParseError> {
     if trimmed.is_empty() {
         return Err(EmptyLine);
     }
-    let mut parts = trimmed.split_whitespace();
     let mut line = Line::new(line_num, trimmed.to_string());
-    match parts.next() {
-        None => {
-            return Err(General(
-                line_num,
-                trimmed.to_string(),
-                String::from("not empty but no contents?"),
-            ))
-        }
-        Some(part) => {
-            if part.ends_with(':') {
-                line.label = Some(part.to_string());
-            } else {
-                line.command = Some((part.to_string(), vec![]));
-            }
-        }
-    }
-    if line.command.is_none() {
-        if let Some(part) = parts.next() {
-            line.command = Some((part.to_string(), vec![]));
+    let tokens = code_tokens(trimmed);
+    let (part, mut remaining) = next_word(&tokens);
+    if part.is_empty() {
+        return Err(General(
+            line_num,
+            trimmed.to_string(),
+            GeneralReason::NoContents,
+        ));
+    } else if let Some(label) = label_name(trimmed, part) {
+        line.label = Some(label.to_string());
+        let (part, after) = next_word(remaining);
+        if !part.is_empty() {
+            line.command = Some((tokens_text(trimmed, part).to_string(), vec![]));
         }
+        remaining = after;
+    } else {
+        line.command = Some((tokens_text(trimmed, part).to_string(), vec![]));
     }
 
-    let remaining = parts.collect::<Vec<&str>>().join(" ");
-    if !remaining.is_empty() {
-        let args = remaining
-            .split(',')
-            .map(|str| str.trim().to_string())
-            .collect::<Vec<String>>();
+    let args = join_args(trimmed, remaining);
+    if !args.is_empty() {
         if let Some(command) = line.command.as_mut() {
             command.1 = args;
         }
@@ -42,8 +36,103 @@ pub fn interpret_line(line_num: usize, trimmed: &str) -> Result<Line, ParseError
     Ok(line)
 }
 
+/// Tokens before the comment, if any
+fn code_tokens(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = tokenize(line);
+    if tokens.last().map(|token| token.kind) == Some(TokenKind::Comment) {
+        tokens.pop();
+    }
+    tokens
+}
+
+/// Returns the label name if the word is `name:`
+fn label_name<'a>(line: &'a str, word: &[Token]) -> Option<&'a str> {
+    match word {
+        [.., last] if word.len() > 1 && last.is_punct(':') => {
+            Some(tokens_text(line, &word[..word.len() - 1]))
+        }
+        _ => None,
+    }
+}
+
+/// Removes comments (anything after a # that isn't in a literal) and surrounding whitespace
+pub fn strip_comment(line: &str) -> &str {
+    split_comment(line).0
+}
+
+/// Splits a line into the code (trimmed) and the comment (starting with #), if any
+pub fn split_comment(line: &str) -> (&str, Option<&str>) {
+    match tokenize(line).last() {
+        Some(token) if token.kind == TokenKind::Comment => {
+            (line[..token.span.start].trim(), Some(token.text))
+        }
+        _ => (line.trim(), None),
+    }
+}
+
+/// Splits off the tokens up to the first whitespace
+fn next_word<'a, 'b>(tokens: &'a [Token<'b>]) -> (&'a [Token<'b>], &'a [Token<'b>]) {
+    let len = tokens
+        .windows(2)
+        .position(|pair| pair[0].span.end != pair[1].span.start)
+        .map(|idx| idx + 1)
+        .unwrap_or(tokens.len());
+    tokens.split_at(len)
+}
+
+/// Each arg with the whitespace between tokens collapsed to a single space
+fn join_args(text: &str, tokens: &[Token]) -> Vec<String> {
+    if tokens.is_empty() {
+        return vec![];
+    }
+    tokens
+        .split(|token| token.is_punct(','))
+        .map(|arg| {
+            let mut output = String::new();
+            for (idx, token) in arg.iter().enumerate() {
+                if idx > 0 && arg[idx - 1].span.end != token.span.start {
+                    output.push(' ');
+                }
+                output.push_str(&text[token.span.clone()]);
+            }
+            output
+        })
+        .collect()
+}
+
+/// Splits on commas and collapses whitespace, except inside char and string literals
+pub fn split_args(text: &str) -> Vec<String> {
+    join_args(text, &code_tokens(text))
+}
+
+/// Byte ranges in `line` of each arg (matching `split_args`)
+pub fn arg_spans(line: &str) -> Vec<Range<usize>> {
+    let tokens = code_tokens(line);
+    let (first, mut remaining) = next_word(&tokens);
+    if label_name(line, first).is_some() {
+        remaining = next_word(remaining).1;
+    }
+    let mut spans = vec![];
+    let mut start = remaining.first().map(|token| token.span.start).unwrap_or(0);
+    let mut span: Option<Range<usize>> = None;
+    for token in remaining {
+        if token.is_punct(',') {
+            spans.push(span.take().unwrap_or(start..start));
+            start = token.span.end;
+        } else {
+            let arg_start = span.map_or(token.span.start, |span| span.start);
+            span = Some(arg_start..token.span.end);
+        }
+    }
+    if !remaining.is_empty() {
+        spans.push(span.unwrap_or(start..start));
+    }
+    spans
+}
+
 #[cfg(test)]
 mod test {
+    use crate::interpreter::{arg_spans, split_comment, strip_comment};
     use crate::{interpret_line, Line};
 
     fn test_op(command: &str, input_args: &str, args: Vec<&str>) {
@@ -86,6 +175,82 @@ mod test {
         );
     }
 
+    #[test]
+    fn literal_tests() {
+        let line = interpret_line(0, "cpy.b al, ','").unwrap();
+        assert_eq!(
+            line.command,
+            Some((
+                String::from("cpy.b"),
+                vec![String::from("al"), String::from("','")]
+            ))
+        );
+
+        let line = interpret_line(0, "cpy.b al,' '#x").unwrap();
+        assert_eq!(
+            line.command,
+            Some((
+                String::from("cpy.b"),
+                vec![String::from("al"), String::from("' '")]
+            ))
+        );
+
+        let line = interpret_line(0, r#"text: .db "a,  b # c\"", 0 # comment"#).unwrap();
+        assert_eq!(line.label, Some(String::from("text")));
+        assert_eq!(
+            line.command,
+            Some((
+                String::from(".db"),
+                vec![String::from(r#""a,  b # c\"""#), String::from("0")]
+            ))
+        );
+    }
+
+    #[test]
+    fn label_tests() {
+        let line = interpret_line(0, "main:").unwrap();
+        assert_eq!(line.label, Some(String::from("main")));
+        assert_eq!(line.command, None);
+
+        let line = interpret_line(0, "  .loop:  inc.b al").unwrap();
+        assert_eq!(line.label, Some(String::from(".loop")));
+        assert_eq!(
+            line.command,
+            Some((String::from("inc.b"), vec![String::from("al")]))
+        );
+
+        let line = interpret_line(0, "@@: jmp @b").unwrap();
+        assert_eq!(line.label, Some(String::from("@@")));
+        assert_eq!(
+            line.command,
+            Some((String::from("jmp"), vec![String::from("@b")]))
+        );
+    }
+
+    #[test]
+    fn comment_tests() {
+        assert_eq!(strip_comment("  inc.b al # test "), "inc.b al");
+        assert_eq!(strip_comment("# test"), "");
+        assert_eq!(strip_comment("cpy.b al, '#' # test"), "cpy.b al, '#'");
+        assert_eq!(strip_comment(r#".db "a#b" # test"#), r#".db "a#b""#);
+        assert_eq!(strip_comment(r##".db "a\"#b" # test"##), r##".db "a\"#b""##);
+        assert_eq!(split_comment(" nop  # test "), ("nop", Some("# test")));
+        assert_eq!(split_comment(" nop "), ("nop", None));
+        assert_eq!(split_comment("#test"), ("", Some("#test")));
+    }
+
+    #[test]
+    fn arg_span_tests() {
+        assert_eq!(arg_spans("cpy.w $100, 1"), vec![6..10, 12..13]);
+        assert_eq!(
+            arg_spans(" main: cpy.b  al , ',' # a, b"),
+            vec![14..16, 19..22]
+        );
+        assert_eq!(arg_spans("mcpy $1, -( bx ),1"), vec![5..7, 9..16, 17..18]);
+        assert!(arg_spans("main: nop").is_empty());
+        assert!(arg_spans("nop # a, b").is_empty());
+    }
+
     #[test]
     fn whitespace_tests() {
         let line = interpret_line(1, "cpy.b ah, ' '").unwrap();
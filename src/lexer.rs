@@ -0,0 +1,213 @@
+use std::ops::Range;
+
+/// Kind of [`Token`], whitespace isn't a token but is reflected in the spans
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum TokenKind {
+    /// Op names, registers, labels, constants and directives, e.g. `inc.b`, `ax`, `.loop`, `@b`
+    Ident,
+    /// Decimal, hex or binary literals, e.g. `12`, `xFF`, `b101`
+    Number,
+    /// Char literal including the quotes, e.g. `'a'`, `'\''`, `'\'`
+    Char,
+    /// String literal including the quotes, e.g. `"a, b"`
+    String,
+    /// Any other single character, e.g. `,`, `:`, `$`, `(`, `+`
+    Punct,
+    /// From `#` to the end of the line
+    Comment,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// Byte range in the tokenized text
+    pub span: Range<usize>,
+}
+
+impl Token<'_> {
+    pub fn is_punct(&self, chr: char) -> bool {
+        self.kind == TokenKind::Punct && self.text.starts_with(chr)
+    }
+}
+
+/// Splits a line into tokens
+///
+/// Unterminated char and string literals run to the end of the line, they are reported when
+/// the literal is parsed
+pub fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, chr)) = chars.next() {
+        if chr.is_whitespace() {
+            continue;
+        }
+        let kind = match chr {
+            '#' => {
+                while chars.next().is_some() {}
+                TokenKind::Comment
+            }
+            '\'' if text[start..].starts_with(r"'\'") && !text[start..].starts_with(r"'\''") => {
+                //a backslash char, e.g. `'\' # c`
+                chars.nth(1);
+                TokenKind::Char
+            }
+            '\'' | '"' => {
+                let mut escaped = false;
+                for (_, next) in chars.by_ref() {
+                    if escaped {
+                        escaped = false;
+                    } else if next == '\\' {
+                        escaped = true;
+                    } else if next == chr {
+                        break;
+                    }
+                }
+                if chr == '\'' {
+                    TokenKind::Char
+                } else {
+                    TokenKind::String
+                }
+            }
+            _ if is_word_char(chr) => {
+                while chars.next_if(|(_, next)| is_word_char(*next)).is_some() {}
+                TokenKind::Ident
+            }
+            _ => TokenKind::Punct,
+        };
+        let end = chars.peek().map(|(idx, _)| *idx).unwrap_or(text.len());
+        let text = &text[start..end];
+        let kind = if kind == TokenKind::Ident && is_number(text) {
+            TokenKind::Number
+        } else {
+            kind
+        };
+        tokens.push(Token {
+            kind,
+            text: text.trim_end(),
+            span: start..start + text.trim_end().len(),
+        });
+    }
+    tokens
+}
+
+fn is_word_char(chr: char) -> bool {
+    chr.is_alphanumeric() || chr == '_' || chr == '.' || chr == '@'
+}
+
+fn is_number(word: &str) -> bool {
+    let is_digits = |digits: &str, radix: u32| {
+        !digits.is_empty() && digits.chars().all(|chr| chr.is_digit(radix))
+    };
+    word.starts_with(|chr: char| chr.is_ascii_digit())
+        || word.strip_prefix('x').is_some_and(|hex| is_digits(hex, 16))
+        || word.strip_prefix('b').is_some_and(|bin| is_digits(bin, 2))
+}
+
+/// Text covered by the tokens (including any whitespace between them)
+pub fn tokens_text<'a>(text: &'a str, tokens: &[Token]) -> &'a str {
+    match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => &text[first.span.start..last.span.end],
+        _ => "",
+    }
+}
+
+/// Splits at the first `chr` that isn't in a literal or comment
+pub fn split_at_punct(text: &str, chr: char) -> Option<(&str, &str)> {
+    tokenize(text)
+        .iter()
+        .find(|token| token.is_punct(chr))
+        .map(|token| (&text[..token.span.start], &text[token.span.end..]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::TokenKind::*;
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(text)
+            .into_iter()
+            .map(|token| (token.kind, token.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            kinds("main: cpy.b al, ' ' # a, b"),
+            vec![
+                (Ident, "main"),
+                (Punct, ":"),
+                (Ident, "cpy.b"),
+                (Ident, "al"),
+                (Punct, ","),
+                (Char, "' '"),
+                (Comment, "# a, b"),
+            ]
+        );
+        assert_eq!(
+            kinds("mcpy $x10, -(bx + Enemy.hp),12"),
+            vec![
+                (Ident, "mcpy"),
+                (Punct, "$"),
+                (Number, "x10"),
+                (Punct, ","),
+                (Punct, "-"),
+                (Punct, "("),
+                (Ident, "bx"),
+                (Punct, "+"),
+                (Ident, "Enemy.hp"),
+                (Punct, ")"),
+                (Punct, ","),
+                (Number, "12"),
+            ]
+        );
+        assert_eq!(
+            kinds(r##".db "a\"#", ',', '\'', b101, bx, xyz"##),
+            vec![
+                (Ident, ".db"),
+                (String, r##""a\"#""##),
+                (Punct, ","),
+                (Char, "','"),
+                (Punct, ","),
+                (Char, r"'\''"),
+                (Punct, ","),
+                (Number, "b101"),
+                (Punct, ","),
+                (Ident, "bx"),
+                (Punct, ","),
+                (Ident, "xyz"),
+            ]
+        );
+        assert_eq!(kinds("@@: jmp @b"), kinds("@@:   jmp   @b"));
+        assert_eq!(kinds("'abc"), vec![(Char, "'abc")]);
+        assert_eq!(
+            kinds(r"cpy.b al, '\' # c"),
+            vec![
+                (Ident, "cpy.b"),
+                (Ident, "al"),
+                (Punct, ","),
+                (Char, r"'\'"),
+                (Comment, "# c"),
+            ]
+        );
+        assert_eq!(kinds(r"'\'"), vec![(Char, r"'\'")]);
+        assert!(tokenize("  ").is_empty());
+    }
+
+    #[test]
+    fn test_spans() {
+        let tokens = tokenize(" é, '#' # x ");
+        let spans: Vec<Range<usize>> = tokens.iter().map(|token| token.span.clone()).collect();
+        assert_eq!(spans, vec![1..3, 3..4, 5..8, 9..12]);
+        assert_eq!(tokens_text(" é, '#' # x ", &tokens[..3]), "é, '#'");
+    }
+
+    #[test]
+    fn test_split_at_punct() {
+        assert_eq!(split_at_punct("a = '='", '='), Some(("a ", " '='")));
+        assert_eq!(split_at_punct("bx + '+'", '+'), Some(("bx ", " '+'")));
+        assert_eq!(split_at_punct("'+' # +", '+'), None);
+    }
+}
@@ -1,48 +1,116 @@
 mod arg_matching;
 mod arg_patterns;
+mod control_blocks;
+mod control_flow;
+mod dead_code;
+mod directives;
+mod disassembler;
+#[cfg(feature = "serde")]
+mod error_serde;
+mod errors;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod formatter;
+mod forms;
 mod interpreter;
+mod lexer;
+mod lint;
+mod listing;
+#[cfg(feature = "lsp")]
+pub mod lsp;
 mod ops;
+mod optimizer;
 mod parsers;
+mod pseudo_ops;
+mod reference;
+mod size;
+mod stack;
+mod strings;
+mod symbols;
+mod warnings;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
+pub use crate::arg_matching::Argument;
 use crate::arg_matching::{arg_list_to_letters, get_op_code};
+use crate::control_blocks::{is_control_directive, read_control_blocks, ControlCode};
+pub use crate::control_flow::{BasicBlock, ControlFlowGraph, Edge, EdgeKind, Flow};
+pub use crate::dead_code::{dead_code, unreachable_lines};
+use crate::directives::{read_data, read_definitions};
+pub use crate::disassembler::{disassemble, DisassembledLine};
+pub use crate::errors::{
+    error_info, error_reference, explain, BlockKind, DirectiveReason, ErrorCategory, ErrorInfo,
+    GeneralReason, LabelReason, RegisterReason, Severity, StringReason, ERRORS,
+};
+pub use crate::formatter::format;
+use crate::forms::{describe_args, describe_forms, pattern_to_args};
+pub use crate::forms::{op_forms, ArgKind, OpForm};
 use crate::interpreter::interpret_line;
-use crate::parsers::parse_argument;
+pub use crate::lint::{lint, lint_program, LintOptions};
+pub use crate::listing::listing;
+pub use crate::optimizer::{
+    optimize, optimize_with_options, Optimization, OptimizationKind, OptimizeOptions, Optimized,
+};
+use crate::parsers::{parse_argument, ArgToken};
+use crate::pseudo_ops::pseudo_op;
+pub use crate::pseudo_ops::{PseudoOp, PSEUDO_OPS};
+pub use crate::reference::instruction_reference;
+pub use crate::size::{label_range, label_size};
+pub use crate::stack::{analyze_stack, Routine, StackAnalysis};
+pub use crate::strings::Charmap;
+use crate::symbols::Symbols;
+use crate::warnings::{allowed_warnings, check_line};
+pub use crate::warnings::{Warning, WarningKind};
+use crate::ParseError::{
+    DeniedWarning, InvalidArguments, MissingArguments, NumberMustBeByte, ProgramTooBig,
+};
+use std::collections::{HashMap, HashSet};
+use std::num::ParseIntError;
 use thiserror::Error;
 
+/// With the `serde` feature this is serialized with named fields, e.g.
+/// `{ "kind": "InvalidOpName", "fields": { "line": 2, "op": "ADD" } }`, see `ErrorReport` to include
+/// the code and message
 #[derive(Error, Debug)]
 pub enum ParseError {
     #[error("Line was empty (internal parser error)")]
     EmptyLine,
     #[error("Unable to parse line {0}: {1} ({2})")]
-    General(usize, String, String),
+    General(usize, String, GeneralReason),
     #[error("Invalid Address format {2}: {1} on line {0}, must be $x0 - $xFFFF")]
-    AddressHexFormat(usize, String, String),
+    AddressHexFormat(usize, String, #[source] ParseIntError),
     #[error("Invalid Address format {2}: {1} on line {0}, must be $0 - $65535")]
-    AddressNumFormat(usize, String, String),
+    AddressNumFormat(usize, String, #[source] ParseIntError),
     #[error(
         "Address out outside of valid range {1} on line {0}, must be less than 65535 or xFFFF"
     )]
     AddressTooBig(usize, String),
     #[error("Invalid Number literal format {2}: {1} on line {0}, must be 0 - 65535")]
-    NumberFormat(usize, String, String),
+    NumberFormat(usize, String, #[source] ParseIntError),
     #[error("Invalid Number literal format {2}: {1} on line {0}, must be x0 - xFFFF")]
-    NumberHexFormat(usize, String, String),
+    NumberHexFormat(usize, String, #[source] ParseIntError),
     #[error("Number literal out outside of valid range {1} on line {0}, must be less than 65535 or xFFFF")]
     NumberTooBig(usize, String),
     #[error("Register has invalid format {1} on line {0}, expected {2}")]
-    InvalidRegister(usize, String, String),
+    InvalidRegister(usize, String, RegisterReason),
     #[error("Invalid Number literal format {2}, {1} on line {0}, must be -32768 to 32767")]
-    SignedNumberNumFormat(usize, String, String),
+    SignedNumberNumFormat(usize, String, #[source] ParseIntError),
     #[error("Invalid Number literal format {1} on line {0}, must be -32768 to 32767")]
     SignedNumberNumRange(usize, String),
     #[error("This instruction only supports byte (0-255), was {1} on line {0}")]
     NumberMustBeByte(usize, String),
     #[error("Instruction unknown/unsupported: {1} {1:02X} on line {0}")]
     InvalidOpCode(usize, u8),
-    #[error("Arguments {1} don't match instruction {2} (line {0}), supported: {3}")]
-    InvalidArguments(usize, String, String, String),
-    #[error("{1} (line {0}) requires arguments, supported: {2}")]
-    MissingArguments(usize, String, String),
+    /// Line, the kinds of arguments found, op name and each accepted list of argument kinds
+    #[error(
+        "Arguments {} don't match instruction {2} (line {0}), supported: {}",
+        describe_args(.1),
+        describe_forms(.2, .3)
+    )]
+    InvalidArguments(usize, Vec<ArgKind>, String, Vec<Vec<ArgKind>>),
+    /// Line, op name and each accepted list of argument kinds
+    #[error("{1} (line {0}) requires arguments, supported: {}", describe_forms(.1, .2))]
+    MissingArguments(usize, String, Vec<Vec<ArgKind>>),
     #[error("No op found named '{1}', maybe you're missing the size? ('.B' or '.W') on line {0}")]
     InvalidOpName(usize, String),
     #[error(
@@ -51,9 +119,137 @@ pub enum ParseError {
     InvalidCharacter(usize, String),
     #[error("Couldn't parse number or register for offset {1} on line {0}")]
     InvalidOffset(usize, String),
+    #[error("Invalid directive {1} on line {0}: {2}")]
+    InvalidDirective(usize, String, DirectiveReason),
+    #[error("{1} on line {0} was never closed, expected {}", .2.end_directive())]
+    UnterminatedBlock(usize, String, BlockKind),
+    #[error("{1} on line {0} has already been defined")]
+    DuplicateSymbol(usize, String),
+    #[error("Invalid string literal {1} on line {0}: {2}")]
+    InvalidString(usize, String, StringReason),
+    #[error("Invalid character '{2}' in string literal {1} on line {0}, must be ASCII")]
+    InvalidStringCharacter(usize, String, char),
+    #[error("Character '{2}' in {1} on line {0} is not in the charmap")]
+    UnmappedCharacter(usize, String, char),
+    #[error("Invalid label {1} on line {0}: {2}")]
+    InvalidLabel(usize, String, LabelReason),
+    #[error("No label or constant named {1} found for line {0}")]
+    UnknownSymbol(usize, String),
+    #[error("Program is larger than 65535 bytes at line {0}")]
+    ProgramTooBig(usize),
+    #[error("{2} on line {0} [{1}] (warnings are errors)")]
+    DeniedWarning(usize, WarningKind, String),
+}
+
+/// A `ParseError` with its code, line and message, e.g. for sending to an IDE as JSON
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorReport {
+    /// See `ParseError::code`
+    pub code: String,
+    pub category: ErrorCategory,
+    pub severity: Severity,
+    pub line: Option<usize>,
+    pub message: String,
+    pub error: ParseError,
+}
+
+impl From<ParseError> for ErrorReport {
+    fn from(error: ParseError) -> Self {
+        ErrorReport {
+            code: error.code().to_string(),
+            category: error.category(),
+            severity: error.severity(),
+            line: error.line_num(),
+            message: error.to_string(),
+            error,
+        }
+    }
 }
 
 impl ParseError {
+    /// Stable code for the error kind, e.g. `E0001`, this doesn't change between versions
+    ///
+    /// See `explain` for a description of each code
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::EmptyLine => "E0000",
+            ParseError::General(..) => "E0001",
+            ParseError::AddressHexFormat(..) => "E0002",
+            ParseError::AddressNumFormat(..) => "E0003",
+            ParseError::AddressTooBig(..) => "E0004",
+            ParseError::NumberFormat(..) => "E0005",
+            ParseError::NumberHexFormat(..) => "E0006",
+            ParseError::NumberTooBig(..) => "E0007",
+            ParseError::InvalidRegister(..) => "E0008",
+            ParseError::SignedNumberNumFormat(..) => "E0009",
+            ParseError::SignedNumberNumRange(..) => "E0010",
+            ParseError::NumberMustBeByte(..) => "E0011",
+            ParseError::InvalidOpCode(..) => "E0012",
+            ParseError::InvalidArguments(..) => "E0013",
+            ParseError::MissingArguments(..) => "E0014",
+            ParseError::InvalidOpName(..) => "E0015",
+            ParseError::InvalidCharacter(..) => "E0016",
+            ParseError::InvalidOffset(..) => "E0017",
+            ParseError::InvalidDirective(..) => "E0018",
+            ParseError::UnterminatedBlock(..) => "E0019",
+            ParseError::DuplicateSymbol(..) => "E0020",
+            ParseError::InvalidString(..) => "E0021",
+            ParseError::InvalidStringCharacter(..) => "E0022",
+            ParseError::UnmappedCharacter(..) => "E0023",
+            ParseError::InvalidLabel(..) => "E0024",
+            ParseError::UnknownSymbol(..) => "E0025",
+            ParseError::ProgramTooBig(..) => "E0026",
+            ParseError::DeniedWarning(..) => "E0027",
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        self.info().category
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.info().severity
+    }
+
+    fn info(&self) -> &'static ErrorInfo {
+        error_info(self.code()).expect("every code is in ERRORS")
+    }
+
+    /// Returns the (zero based) line number the error occurred on
+    pub fn line_num(&self) -> Option<usize> {
+        match self {
+            ParseError::EmptyLine => None,
+            ParseError::General(line_num, _, _)
+            | ParseError::AddressHexFormat(line_num, _, _)
+            | ParseError::AddressNumFormat(line_num, _, _)
+            | ParseError::AddressTooBig(line_num, _)
+            | ParseError::NumberFormat(line_num, _, _)
+            | ParseError::NumberHexFormat(line_num, _, _)
+            | ParseError::NumberTooBig(line_num, _)
+            | ParseError::InvalidRegister(line_num, _, _)
+            | ParseError::SignedNumberNumFormat(line_num, _, _)
+            | ParseError::SignedNumberNumRange(line_num, _)
+            | ParseError::NumberMustBeByte(line_num, _)
+            | ParseError::InvalidOpCode(line_num, _)
+            | ParseError::InvalidArguments(line_num, _, _, _)
+            | ParseError::MissingArguments(line_num, _, _)
+            | ParseError::InvalidOpName(line_num, _)
+            | ParseError::InvalidCharacter(line_num, _)
+            | ParseError::InvalidOffset(line_num, _)
+            | ParseError::InvalidDirective(line_num, _, _)
+            | ParseError::UnterminatedBlock(line_num, _, _)
+            | ParseError::DuplicateSymbol(line_num, _)
+            | ParseError::InvalidString(line_num, _, _)
+            | ParseError::InvalidStringCharacter(line_num, _, _)
+            | ParseError::UnmappedCharacter(line_num, _, _)
+            | ParseError::InvalidLabel(line_num, _, _)
+            | ParseError::UnknownSymbol(line_num, _)
+            | ParseError::ProgramTooBig(line_num)
+            | ParseError::DeniedWarning(line_num, _, _) => Some(*line_num),
+        }
+    }
+
     fn num_to_addr(self) -> Self {
         match self {
             ParseError::NumberFormat(line_num, msg, err) => {
@@ -69,6 +265,7 @@ impl ParseError {
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Line {
     pub num: usize,
     pub original: String,
@@ -88,71 +285,331 @@ impl Line {
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
     pub lines: Vec<ParsedLine>,
     pub bytes: Vec<u8>,
+    /// Full name (e.g. `main` or `main.loop`) and address of each label
+    pub labels: HashMap<String, u16>,
+    /// Warnings found while parsing, excluding any allowed by `# lint: allow(name)` comments
+    pub warnings: Vec<Warning>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParsedLine {
     pub line: Line,
+    pub address: u16,
     pub bytes: Vec<u8>,
+    /// Arguments in the same order as `line.command`, empty for directives
+    pub arguments: Vec<Argument>,
+    /// Real ops for pseudo-ops (e.g. `CLR.B al` is `CPY.B al, 0`), empty otherwise
+    pub expansion: Vec<ParsedLine>,
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct ParseOptions {
+    /// Maps characters to bytes for char literals and strings
+    ///
+    /// If set (either here or by `.charmap`) then all characters used must be in the map
+    pub charmap: Option<Charmap>,
+    /// Warnings that won't be reported
+    pub allowed_warnings: HashSet<WarningKind>,
+    /// If true the first warning is returned as `DeniedWarning`
+    pub warnings_as_errors: bool,
+    /// If true instructions found by `unreachable_lines` aren't assembled
+    ///
+    /// Labels after removed code move, so numeric addresses (e.g. `JMP $20`) may become invalid
+    pub strip_unreachable: bool,
 }
 
 pub fn parse_program(lines: &[&str]) -> Result<Program, ParseError> {
-    let mut output = vec![];
-    for (idx, line) in lines.iter().enumerate() {
-        let trimmed = line.trim();
-        let (trimmed, _) = trimmed.split_once('#').unwrap_or((trimmed, ""));
-        if !trimmed.is_empty() {
-            let line = interpret_line(idx, line)?;
-            output.push(parse_line(line)?);
+    parse_program_with_options(lines, &ParseOptions::default())
+}
+
+pub fn parse_program_with_options(
+    lines: &[&str],
+    options: &ParseOptions,
+) -> Result<Program, ParseError> {
+    let source = lines;
+    let symbols = Symbols::with_charmap(options.charmap.clone());
+    let (mut symbols, code) = read_definitions(source, symbols)?;
+    let mut lines = vec![];
+    for (idx, line) in code {
+        let mut line = interpret_line(idx, line)?;
+        if let Some(label) = &line.label {
+            line.label = Some(symbols.add_label(idx, label)?);
+        }
+        lines.push(line);
+    }
+    read_control_blocks(&lines, &mut symbols)?;
+    //labels are always two bytes so the size of each line can be
+    //calculated before the addresses are known
+    let mut address = 0;
+    for line in &lines {
+        if let Some(label) = &line.label {
+            symbols.set_label_address(line.num, label, address);
+        }
+        let control = symbols.control_code(line.num).cloned().unwrap_or_default();
+        if let Some(label) = &control.label_before {
+            symbols.set_hidden_label_address(label, address);
+        }
+        let parsed = parse_line(line.clone(), &symbols, address)?;
+        address = next_address(&parsed)?;
+        if let Some(label) = &control.label_after {
+            symbols.set_hidden_label_address(label, address);
+        }
+    }
+    let mut output: Vec<ParsedLine> = vec![];
+    let mut warnings = vec![];
+    let mut address = 0;
+    for line in lines {
+        let parsed = parse_line(line, &symbols, address)?;
+        address = next_address(&parsed)?;
+        warnings.extend(check_line(source, &parsed, output.last()));
+        output.push(parsed);
+    }
+    let allowed = allowed_warnings(source);
+    warnings.retain(|warning: &Warning| {
+        !options.allowed_warnings.contains(&warning.kind)
+            && !allowed
+                .get(warning.line_num)
+                .map(|kinds| kinds.contains(&warning.kind))
+                .unwrap_or_default()
+    });
+    if options.warnings_as_errors {
+        if let Some(warning) = warnings.first() {
+            return Err(DeniedWarning(
+                warning.line_num,
+                warning.kind,
+                warning.message.clone(),
+            ));
         }
     }
     let bytes = output.iter().flat_map(|line| line.bytes.clone()).collect();
     let program = Program {
         lines: output,
         bytes,
+        labels: symbols.labels().clone(),
+        warnings,
     };
+    if options.strip_unreachable {
+        let unreachable: Vec<usize> = unreachable_lines(&program)
+            .into_iter()
+            .map(|idx| program.lines[idx].line.num)
+            .collect();
+        if !unreachable.is_empty() {
+            //unreachable lines never have labels so can be removed
+            let stripped: Vec<&str> = source
+                .iter()
+                .enumerate()
+                .map(|(num, line)| {
+                    if unreachable.contains(&num) {
+                        ""
+                    } else {
+                        *line
+                    }
+                })
+                .collect();
+            let options = ParseOptions {
+                strip_unreachable: false,
+                ..options.clone()
+            };
+            return parse_program_with_options(&stripped, &options);
+        }
+    }
     Ok(program)
 }
 
-fn parse_line(line: Line) -> Result<ParsedLine, ParseError> {
+fn next_address(line: &ParsedLine) -> Result<u16, ParseError> {
+    let next = line.address as usize + line.bytes.len();
+    if next > u16::MAX as usize + 1 {
+        Err(ProgramTooBig(line.line.num))
+    } else {
+        Ok(next as u16)
+    }
+}
+
+fn parse_line(line: Line, symbols: &Symbols, address: u16) -> Result<ParsedLine, ParseError> {
     let mut bytes = vec![];
+    let mut arguments = vec![];
     if let Some((op, args)) = &line.command {
+        if is_control_directive(op) {
+            if let Some(control) = symbols.control_code(line.num) {
+                return parse_control_code(line, control, symbols, address);
+            }
+        }
+        if op.starts_with('.') {
+            let bytes = read_data(line.num, op, args, symbols)?;
+            return Ok(ParsedLine {
+                line,
+                address,
+                bytes,
+                arguments: vec![],
+                expansion: vec![],
+            });
+        }
         let command = op.to_ascii_uppercase();
-        let mut arguments = vec![];
+        if let Some(pseudo) = pseudo_op(&command) {
+            return parse_pseudo_op(line, pseudo, symbols, address);
+        }
         let expects_bytes = ops::expects_bytes(&command);
         for arg in args {
-            let arg_token = parse_argument(line.num, arg)?;
+            let arg_token = parse_argument(line.num, arg, symbols)?;
+            //only constants, literals that are too big still give InvalidArguments
+            if let ArgToken::Number(num) = arg_token {
+                if expects_bytes && num > u8::MAX as u16 && symbols.constant(arg.trim()).is_some() {
+                    return Err(NumberMustBeByte(line.num, arg.to_string()));
+                }
+            }
             arguments.push(arg_token.to_argument(expects_bytes));
         }
         let pattern = arg_list_to_letters(&arguments);
-        bytes.push(get_op_code(line.num, &command, &pattern)?);
+        let op_code = match get_op_code(line.num, &command, &pattern) {
+            Ok(op_code) => op_code,
+            Err(err) => {
+                //numbers default to words for ops without a size (e.g. BMUL), so try bytes
+                let byte_args = words_to_bytes(&command, &arguments);
+                match get_op_code(line.num, &command, &arg_list_to_letters(&byte_args)) {
+                    Ok(op_code) if byte_args != arguments => {
+                        arguments = byte_args;
+                        op_code
+                    }
+                    _ => return Err(err),
+                }
+            }
+        };
+        bytes.push(op_code);
         for arg in &arguments {
             bytes.extend_from_slice(&arg.to_bytes());
         }
-        for arg in arguments {
+        for arg in &arguments {
             bytes.extend_from_slice(&arg.to_offset_bytes());
         }
     }
-    Ok(ParsedLine { line, bytes })
+    Ok(ParsedLine {
+        line,
+        address,
+        bytes,
+        arguments,
+        expansion: vec![],
+    })
+}
+
+/// Pseudo-op args are checked against the pseudo-op patterns, and then each real op is parsed
+fn parse_pseudo_op(
+    line: Line,
+    pseudo: &PseudoOp,
+    symbols: &Symbols,
+    address: u16,
+) -> Result<ParsedLine, ParseError> {
+    let args = line
+        .command
+        .as_ref()
+        .map(|(_, args)| args.clone())
+        .unwrap_or_default();
+    let expects_bytes = ops::expects_bytes(pseudo.name);
+    let mut arguments = vec![];
+    for arg in &args {
+        arguments.push(parse_argument(line.num, arg, symbols)?.to_argument(expects_bytes));
+    }
+    let pattern = arg_list_to_letters(&arguments);
+    if !pseudo.patterns.contains(&pattern.as_str()) {
+        let forms = pseudo
+            .patterns
+            .iter()
+            .map(|pattern| pattern_to_args(pattern))
+            .collect();
+        return Err(if pattern.is_empty() {
+            MissingArguments(line.num, pseudo.name.to_string(), forms)
+        } else {
+            InvalidArguments(
+                line.num,
+                pattern_to_args(&pattern),
+                pseudo.name.to_string(),
+                forms,
+            )
+        });
+    }
+    let mut expansion = vec![];
+    let mut next = address;
+    for text in pseudo.expand(&args) {
+        let parsed = parse_line(interpret_line(line.num, &text)?, symbols, next)?;
+        next = next_address(&parsed)?;
+        expansion.push(parsed);
+    }
+    Ok(ParsedLine {
+        line,
+        address,
+        bytes: expansion
+            .iter()
+            .flat_map(|part| part.bytes.clone())
+            .collect(),
+        arguments,
+        expansion,
+    })
+}
+
+/// Control directives (e.g. `.if_eq`) are replaced with their generated code
+fn parse_control_code(
+    line: Line,
+    control: &ControlCode,
+    symbols: &Symbols,
+    address: u16,
+) -> Result<ParsedLine, ParseError> {
+    let mut expansion = vec![];
+    let mut next = address;
+    for text in &control.code {
+        let parsed = parse_line(interpret_line(line.num, text)?, symbols, next)?;
+        next = next_address(&parsed)?;
+        expansion.push(parsed);
+    }
+    Ok(ParsedLine {
+        line,
+        address,
+        bytes: expansion
+            .iter()
+            .flat_map(|part| part.bytes.clone())
+            .collect(),
+        arguments: vec![],
+        expansion,
+    })
+}
+
+/// Converts words that fit in a byte, unless `command` is a word op
+fn words_to_bytes(command: &str, arguments: &[Argument]) -> Vec<Argument> {
+    arguments
+        .iter()
+        .map(|arg| match arg {
+            Argument::Word(num) if !command.ends_with(".W") && *num <= u8::MAX as u16 => {
+                Argument::Byte(*num as u8)
+            }
+            _ => arg.clone(),
+        })
+        .collect()
 }
 
 pub fn parse_line_from_str(text: &str) -> Result<ParsedLine, ParseError> {
     let line = interpret_line(0, text)?;
-    parse_line(line)
+    parse_line(line, &Symbols::default(), 0)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use maikor_platform::op_params::{IND_OFFSET_REG, IND_PRE_DEC};
+    use crate::ParseError::{
+        DuplicateSymbol, InvalidArguments, InvalidDirective, InvalidLabel, InvalidStringCharacter,
+        MissingArguments, NumberHexFormat, UnknownSymbol, UnmappedCharacter,
+    };
+    use maikor_platform::op_params::{IND_OFFSET_NUM, IND_OFFSET_REG, IND_PRE_DEC};
     use maikor_platform::ops::{
-        ADD_REG_NUM_BYTE, ADD_REG_NUM_WORD, CMP_REG_NUM_BYTE, INC_REG_BYTE, INC_REG_WORD, JE_ADDR,
-        MEM_CPY_ADDR_REG_BYTE,
+        ADD_REG_NUM_BYTE, ADD_REG_NUM_WORD, BMUL_ADDR_NUM, CALL_ADDR, CMP_REG_NUM_BYTE,
+        CMP_REG_NUM_WORD, CPY_REG_NUM_BYTE, CPY_REG_NUM_WORD, DEC_REG_BYTE, HALT, INC_REG_BYTE,
+        INC_REG_WORD, JE_ADDR, JMP_ADDR, JNE_ADDR, MEM_CPY_ADDR_REG_BYTE, NOP, NOT_REG_BYTE,
+        ROL_REG_NUM_WORD,
     };
     use maikor_platform::registers::id;
+    use std::num::IntErrorKind;
 
     #[test]
     fn line_test() {
@@ -165,7 +622,10 @@ mod test {
                     label: None,
                     command: Some(("inc.w".to_string(), vec!["bx".to_string()])),
                 },
-                bytes: vec![INC_REG_WORD, id::BX as u8],
+                address: 0,
+                bytes: vec![INC_REG_WORD, id::BX],
+                arguments: vec![Argument::ExtReg(id::BX)],
+                expansion: vec![],
             }
         );
         assert_eq!(
@@ -180,13 +640,99 @@ mod test {
                         vec!["al".to_string(), "30".to_string()]
                     )),
                 },
-                bytes: vec![ADD_REG_NUM_BYTE, id::AL as u8, 30],
+                address: 0,
+                bytes: vec![ADD_REG_NUM_BYTE, id::AL, 30],
+                arguments: vec![Argument::Register(id::AL), Argument::Byte(30)],
+                expansion: vec![],
             }
         );
 
         assert!(parse_line_from_str("inc al").is_err());
     }
 
+    #[test]
+    fn error_message_test() {
+        let err = parse_line_from_str("inc.b $1, al").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Arguments addr, reg8 don't match instruction INC.B (line 0), supported: INC.B (reg16); INC.B reg8; INC.B addr"
+        );
+        let err = parse_line_from_str("inc.b").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "INC.B (line 0) requires arguments, supported: INC.B (reg16); INC.B reg8; INC.B addr"
+        );
+    }
+
+    #[test]
+    fn error_payload_test() {
+        use std::error::Error;
+
+        let err = parse_line_from_str("inc.b $1, al").unwrap_err();
+        match err {
+            InvalidArguments(0, found, op, forms) => {
+                assert_eq!(found, vec![ArgKind::Address, ArgKind::Register]);
+                assert_eq!(op, "INC.B");
+                assert!(forms.contains(&vec![ArgKind::Register]));
+            }
+            err => panic!("unexpected {err}"),
+        }
+        let err = parse_line_from_str("inc.b").unwrap_err();
+        assert!(matches!(err, MissingArguments(0, op, forms) if op == "INC.B" && forms.len() == 3));
+
+        let err = parse_line_from_str("cpy.b al, xZZ").unwrap_err();
+        assert!(
+            matches!(&err, NumberHexFormat(0, _, err) if *err.kind() == IntErrorKind::InvalidDigit)
+        );
+        assert!(err.source().unwrap().is::<ParseIntError>());
+    }
+
+    #[test]
+    fn pseudo_op_test() {
+        let program = parse_program(&["main: clr.b al", "  neg.w ax", "  jmp main"]).unwrap();
+        let expected =
+            parse_program(&["main: cpy.b al, 0", "not.w ax", "inc.w ax", "jmp main"]).unwrap();
+        assert_eq!(program.bytes, expected.bytes);
+        assert_eq!(program.lines[1].address, 3);
+        let parts: Vec<(u16, &str)> = program.lines[1]
+            .expansion
+            .iter()
+            .map(|part| (part.address, part.line.original.as_str()))
+            .collect();
+        assert_eq!(parts, vec![(3, "NOT.W ax"), (5, "INC.W ax")]);
+        assert_eq!(program.lines[1].arguments, vec![Argument::ExtReg(id::AX)]);
+
+        let program =
+            parse_program(&["swapb ax", "clr.w bx", "tst.b al", "tst.w cx", "neg.b bl"]).unwrap();
+        let bytes: Vec<Vec<u8>> = program
+            .lines
+            .iter()
+            .map(|line| line.bytes.clone())
+            .collect();
+        assert_eq!(
+            bytes,
+            vec![
+                vec![ROL_REG_NUM_WORD, id::AX, 0, 8],
+                vec![CPY_REG_NUM_WORD, id::BX, 0, 0],
+                vec![CMP_REG_NUM_BYTE, id::AL, 0],
+                vec![CMP_REG_NUM_WORD, id::CX, 0, 0],
+                vec![NOT_REG_BYTE, id::BL, INC_REG_BYTE, id::BL],
+            ]
+        );
+        assert_eq!(bytes[0], parse_line_from_str("rol.w ax, 8").unwrap().bytes);
+
+        let err = parse_line_from_str("neg.b (bx)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Arguments (reg16) don't match instruction NEG.B (line 0), supported: NEG.B reg8"
+        );
+        let err = parse_line_from_str("swapb").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "SWAPB (line 0) requires arguments, supported: SWAPB reg16"
+        );
+    }
+
     #[test]
     fn basic_test() {
         let lines = vec!["# test program", "INC.B AL", "CMP.B AL, 1", "JE $50"];
@@ -207,6 +753,225 @@ mod test {
         );
     }
 
+    #[test]
+    fn struct_test() {
+        let lines = vec![
+            ".struct Enemy",
+            "  .b hp",
+            "  .w pos",
+            ".ends",
+            "cpy.b (bx + Enemy.hp), 10",
+            "add.w bx, Enemy.SIZE",
+        ];
+        let output = parse_program(&lines).unwrap();
+        assert_eq!(output.lines.len(), 2);
+        assert_eq!(
+            output.bytes,
+            vec![
+                CPY_REG_NUM_BYTE,
+                id::BX | IND_OFFSET_NUM,
+                10,
+                0,
+                0,
+                ADD_REG_NUM_WORD,
+                id::BX,
+                0,
+                3
+            ]
+        );
+    }
+
+    #[test]
+    fn enum_test() {
+        let lines = vec![
+            ".enum State, 254",
+            "  idle",
+            "  walking",
+            "  dead",
+            ".ende",
+            "cmp.b al, State.walking",
+            "cpy.w bx, State.dead",
+        ];
+        let output = parse_program(&lines).unwrap();
+        assert_eq!(
+            output.bytes,
+            vec![
+                CMP_REG_NUM_BYTE,
+                id::AL,
+                255,
+                CPY_REG_NUM_WORD,
+                id::BX,
+                1,
+                0
+            ]
+        );
+
+        let lines = vec![
+            ".enum State, 255",
+            "  idle",
+            "  dead",
+            ".ende",
+            "cmp.b al, State.dead",
+        ];
+        assert!(matches!(parse_program(&lines), Err(NumberMustBeByte(4, _))));
+        //plain literals keep the error they had before constants
+        assert!(matches!(
+            parse_line_from_str("cpy.b al, 300"),
+            Err(InvalidArguments(0, _, _, _))
+        ));
+    }
+
+    #[test]
+    fn data_test() {
+        let lines = vec![
+            ".db 1, 'A', \"a, b\\n\" # comment",
+            ".dw 256",
+            ".strz \"#\"",
+            ".strl \"Hi\"",
+        ];
+        let output = parse_program(&lines).unwrap();
+        assert_eq!(output.lines.len(), 4);
+        assert_eq!(
+            output.bytes,
+            vec![1, 65, 97, 44, 32, 98, 10, 1, 0, 35, 0, 2, 72, 105]
+        );
+        assert_eq!(
+            parse_line_from_str(r"cpy.b al, '\' # c").unwrap().arguments[1],
+            Argument::Byte(92)
+        );
+        assert_eq!(
+            parse_program(&[r".db '\', 1 # c"]).unwrap().bytes,
+            vec![92, 1]
+        );
+
+        assert!(matches!(
+            parse_program(&[".db \"ü\""]),
+            Err(InvalidStringCharacter(0, _, 'ü'))
+        ));
+        assert!(matches!(
+            parse_program(&[".foo 1"]),
+            Err(InvalidDirective(0, _, _))
+        ));
+    }
+
+    #[test]
+    fn charmap_test() {
+        let lines = vec![
+            ".charmap \"ABCDEFGHIJKLMNOPQRSTUVWXYZ\", 1",
+            ".charmap ' ', 0",
+            "cpy.b al, 'C'",
+            ".strz \"HI YOU\"",
+        ];
+        let output = parse_program(&lines).unwrap();
+        assert_eq!(
+            output.bytes,
+            vec![CPY_REG_NUM_BYTE, id::AL, 3, 8, 9, 0, 25, 15, 21, 0]
+        );
+
+        let options = ParseOptions {
+            charmap: Some(Charmap::from([('a', 100), ('b', 101)])),
+            ..ParseOptions::default()
+        };
+        let lines = vec![".charmap 'c', 50", ".db \"abc\", 'b'"];
+        let output = parse_program_with_options(&lines, &options).unwrap();
+        assert_eq!(output.bytes, vec![100, 101, 50, 101]);
+
+        assert!(matches!(
+            parse_program_with_options(&["cpy.b al, 'd'"], &options),
+            Err(UnmappedCharacter(0, _, 'd'))
+        ));
+        assert!(matches!(
+            parse_program_with_options(&[".db \"ad\""], &options),
+            Err(UnmappedCharacter(0, _, 'd'))
+        ));
+    }
+
+    #[test]
+    fn label_test() {
+        let lines = vec![
+            "main:",
+            "  cpy.b al, 0",
+            ".loop:",
+            "  inc.b al",
+            "  cmp.b al, 10",
+            "  jne .loop",
+            "  call other",
+            "  halt",
+            "other:",
+            "@@: dec.b al",
+            "  jne @b",
+            "  jmp +",
+            "-: nop",
+            "+: jmp -",
+            "  jmp main.loop",
+            "table: .dw main, other",
+        ];
+        let output = parse_program(&lines).unwrap();
+        assert_eq!(output.labels.len(), 4);
+        assert_eq!(output.labels["main"], 0);
+        assert_eq!(output.labels["main.loop"], 3);
+        assert_eq!(output.labels["other"], 15);
+        assert_eq!(output.labels["table"], 30);
+        assert_eq!(output.lines[2].line.label, Some(String::from("main.loop")));
+        assert_eq!(output.lines[2].address, 3);
+        assert_eq!(
+            output.bytes,
+            vec![
+                CPY_REG_NUM_BYTE,
+                id::AL,
+                0,
+                INC_REG_BYTE,
+                id::AL,
+                CMP_REG_NUM_BYTE,
+                id::AL,
+                10,
+                JNE_ADDR,
+                0,
+                3,
+                CALL_ADDR,
+                0,
+                15,
+                HALT,
+                DEC_REG_BYTE,
+                id::AL,
+                JNE_ADDR,
+                0,
+                15,
+                JMP_ADDR,
+                0,
+                24,
+                NOP,
+                JMP_ADDR,
+                0,
+                23,
+                JMP_ADDR,
+                0,
+                3,
+                0,
+                0,
+                0,
+                15
+            ]
+        );
+
+        assert!(matches!(
+            parse_program(&["jmp missing"]),
+            Err(UnknownSymbol(0, _))
+        ));
+        assert!(matches!(
+            parse_program(&["main:", "jmp .missing"]),
+            Err(UnknownSymbol(1, _))
+        ));
+        assert!(matches!(
+            parse_program(&["main:", "main:"]),
+            Err(DuplicateSymbol(1, _))
+        ));
+        assert!(matches!(
+            parse_program(&[".loop:"]),
+            Err(InvalidLabel(0, _, _))
+        ));
+    }
+
     #[test]
     fn whitespace_test() {
         let lines = vec![
@@ -234,4 +999,225 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn warnings_test() {
+        let lines = vec![
+            "cpy.w ax, -1",
+            "cpy.b al, 1",
+            "cpy.b al, 1",
+            "nop",
+            "nop",
+            "bmul $1, 2 # lint: allow(implicit_size)",
+            "cpy.w bx, -2 # lint: allow(negative_number)",
+            "inc.b al",
+            "inc.b al",
+            "push.w ax",
+            "push.w ax",
+            "cpy.b (bx)+, 1",
+            "cpy.b (bx)+, 1",
+            "cpy.b $100, 1",
+            "cpy.b $100, 1",
+            "clr.w ax",
+            "CLR.W ax",
+        ];
+        let output = parse_program(&lines).unwrap();
+        let warnings: Vec<(usize, WarningKind)> = output
+            .warnings
+            .iter()
+            .map(|warning| (warning.line_num, warning.kind))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                (0, WarningKind::NegativeNumber),
+                (2, WarningKind::DuplicateLine),
+                (16, WarningKind::DuplicateLine)
+            ]
+        );
+        assert_eq!(output.warnings[0].span, 10..12);
+        assert_eq!(output.warnings[0].message, "-1 is stored as 65535");
+        assert_eq!(output.lines[5].bytes, vec![BMUL_ADDR_NUM, 0, 1, 2]);
+
+        let options = ParseOptions {
+            allowed_warnings: HashSet::from([WarningKind::NegativeNumber]),
+            ..ParseOptions::default()
+        };
+        let output = parse_program_with_options(&lines, &options).unwrap();
+        assert_eq!(output.warnings.len(), 2);
+
+        let options = ParseOptions {
+            warnings_as_errors: true,
+            ..ParseOptions::default()
+        };
+        assert!(matches!(
+            parse_program_with_options(&lines, &options),
+            Err(DeniedWarning(0, WarningKind::NegativeNumber, _))
+        ));
+        assert!(parse_program_with_options(&lines[3..15], &options).is_ok());
+    }
+
+    #[test]
+    fn strip_unreachable_test() {
+        let lines = [
+            "main: jmp .end",
+            "  inc.b al",
+            "  inc.b ah # unreachable",
+            ".end: halt",
+        ];
+        let options = ParseOptions {
+            strip_unreachable: true,
+            ..ParseOptions::default()
+        };
+        let output = parse_program_with_options(&lines, &options).unwrap();
+        assert_eq!(output.bytes, vec![JMP_ADDR, 0, 3, HALT]);
+        assert_eq!(output.labels.get("main.end"), Some(&3));
+        let nums: Vec<usize> = output.lines.iter().map(|line| line.line.num).collect();
+        assert_eq!(nums, vec![0, 3]);
+        assert_eq!(parse_program(&lines).unwrap().bytes.len(), 8);
+    }
+
+    #[test]
+    fn error_code_test() {
+        let err = parse_program(&["nop", "add.b ax, 1"]).unwrap_err();
+        assert_eq!(err.code(), "E0013");
+        assert_eq!(ParseError::EmptyLine.code(), "E0000");
+        assert_eq!(ProgramTooBig(1).code(), "E0026");
+        assert_eq!(err.category(), ErrorCategory::OpMatching);
+        assert_eq!(err.severity(), Severity::Error);
+        assert_eq!(ParseError::EmptyLine.severity(), Severity::Bug);
+        let int_err = || "a".parse::<u8>().unwrap_err();
+        let text = String::new;
+        //one of each variant, in code order
+        let errors = [
+            ParseError::EmptyLine,
+            ParseError::General(0, text(), GeneralReason::NoContents),
+            ParseError::AddressHexFormat(0, text(), int_err()),
+            ParseError::AddressNumFormat(0, text(), int_err()),
+            ParseError::AddressTooBig(0, text()),
+            ParseError::NumberFormat(0, text(), int_err()),
+            ParseError::NumberHexFormat(0, text(), int_err()),
+            ParseError::NumberTooBig(0, text()),
+            ParseError::InvalidRegister(0, text(), RegisterReason::Unclosed),
+            ParseError::SignedNumberNumFormat(0, text(), int_err()),
+            ParseError::SignedNumberNumRange(0, text()),
+            ParseError::NumberMustBeByte(0, text()),
+            ParseError::InvalidOpCode(0, 0),
+            ParseError::InvalidArguments(0, vec![], text(), vec![]),
+            ParseError::MissingArguments(0, text(), vec![]),
+            ParseError::InvalidOpName(0, text()),
+            ParseError::InvalidCharacter(0, text()),
+            ParseError::InvalidOffset(0, text()),
+            ParseError::InvalidDirective(0, text(), DirectiveReason::NoBlockToEnd),
+            ParseError::UnterminatedBlock(0, text(), BlockKind::If),
+            ParseError::DuplicateSymbol(0, text()),
+            ParseError::InvalidString(0, text(), StringReason::Unquoted),
+            ParseError::InvalidStringCharacter(0, text(), 'a'),
+            ParseError::UnmappedCharacter(0, text(), 'a'),
+            ParseError::InvalidLabel(0, text(), LabelReason::NoScope),
+            ParseError::UnknownSymbol(0, text()),
+            ParseError::ProgramTooBig(0),
+            ParseError::DeniedWarning(0, WarningKind::UnusedLabel, text()),
+        ];
+        assert_eq!(errors.len(), ERRORS.len());
+        for (err, expected) in errors.iter().zip(ERRORS.iter()) {
+            let debug = format!("{err:?}");
+            let name = debug.split('(').next().unwrap();
+            assert_eq!(err.code(), expected.code, "{name}");
+            let info = error_info(err.code()).unwrap();
+            assert_eq!(info.name, name);
+            assert_eq!(err.category(), info.category, "{name}");
+            assert_eq!(err.severity(), info.severity, "{name}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_test() {
+        use serde_json::json;
+
+        let program = parse_program(&["main: cpy.w ax, -1", "  jmp main"]).unwrap();
+        let json = serde_json::to_value(&program).unwrap();
+        assert_eq!(json["labels"], json!({ "main": 0 }));
+        assert_eq!(
+            json["lines"][0]["arguments"],
+            json!([{ "ExtReg": 9 }, { "Word": 65535 }])
+        );
+        assert_eq!(
+            json["lines"][1]["line"]["command"],
+            json!(["jmp", ["main"]])
+        );
+        assert_eq!(
+            json["warnings"][0],
+            json!({
+                "line_num": 0,
+                "span": { "start": 16, "end": 18 },
+                "kind": "negative_number",
+                "message": "-1 is stored as 65535",
+            })
+        );
+        let decoded: Program = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded, program);
+
+        let err = parse_program(&["nop", "nop al"]).unwrap_err();
+        let report = serde_json::to_value(ErrorReport::from(err)).unwrap();
+        assert_eq!(report["code"], "E0013");
+        assert_eq!(report["category"], "op_matching");
+        assert_eq!(report["severity"], "error");
+        assert_eq!(report["line"], 1);
+        assert_eq!(report["error"]["kind"], "InvalidArguments");
+        assert_eq!(report["error"]["fields"]["line"], 1);
+        assert_eq!(report["error"]["fields"]["op"], "NOP");
+        assert_eq!(report["error"]["fields"]["found"], json!(["Register"]));
+        let decoded: ErrorReport = serde_json::from_value(report).unwrap();
+        assert!(matches!(decoded.error, InvalidArguments(1, _, _, _)));
+
+        let err = parse_line_from_str("cpy.b al, xZZ").unwrap_err();
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "kind": "NumberHexFormat",
+                "fields": { "line": 0, "text": "xZZ", "error": "invalid_digit" },
+            })
+        );
+        let decoded: ParseError = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.to_string(), err.to_string());
+
+        for kind in [
+            "empty",
+            "invalid_digit",
+            "pos_overflow",
+            "neg_overflow",
+            "zero",
+        ] {
+            let json = json!({ "kind": "NumberFormat", "fields": { "line": 0, "text": "", "error": kind } });
+            let decoded: ParseError = serde_json::from_value(json.clone()).unwrap();
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), json);
+        }
+        let err = parse_program(&[".if_eq", "nop"]).unwrap_err();
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "kind": "UnterminatedBlock",
+                "fields": { "line": 0, "block": ".if_eq", "block_kind": "if" },
+            })
+        );
+        let err = parse_program(&[".if_eq", ".endif", ".else"]).unwrap_err();
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["fields"]["reason"], "no_block_to_end");
+        let err = parse_program(&[".if_xx", ".endif"]).unwrap_err();
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(
+            json["fields"]["reason"],
+            json!({ "unknown_condition": "xx" })
+        );
+        let decoded: ParseError = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.to_string(), err.to_string());
+
+        let json = json!({ "kind": "NumberFormat", "fields": { "line": 0, "text": "", "error": "unknown" } });
+        let err = serde_json::from_value::<ParseError>(json).unwrap_err();
+        assert!(err.to_string().contains("unknown int error unknown"));
+    }
 }
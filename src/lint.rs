@@ -0,0 +1,344 @@
+use crate::arg_matching::Argument;
+use crate::arg_patterns::ARG_MATCHES;
+use crate::dead_code::dead_code;
+use crate::stack::analyze_stack;
+use crate::warnings::{allowed_warnings, arg_span, warning, Warning, WarningKind};
+use crate::{parse_program, ParseError, ParsedLine, Program};
+use maikor_platform::registers::id;
+use std::collections::HashSet;
+
+/// Ops that don't write to any of their args
+const READ_ONLY_OPS: [&str; 10] = [
+    "CMP.B", "CMP.W", "CMPS.B", "CMPS.W", "PUSH.B", "PUSH.W", "JBC", "JBS", "MCPY", "MSWP",
+];
+/// Ops that write to all of their args
+const SWAP_OPS: [&str; 2] = ["SWAP.B", "SWAP.W"];
+/// `POP.B` writes to `FLG` but isn't reported, as `PUSH.B FLG` ... `POP.B FLG` is how the
+/// flags are saved and restored
+const FLAGS_RESTORE_OP: &str = "POP.B";
+/// Ops that jump to their (only) arg
+const JUMP_OPS: [&str; 8] = ["JMP", "JE", "JNE", "JL", "JG", "JLE", "JGE", "CALL"];
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct LintOptions {
+    /// Lints that won't be reported
+    pub disabled: HashSet<WarningKind>,
+}
+
+impl LintOptions {
+    pub fn is_enabled(&self, kind: WarningKind) -> bool {
+        !self.disabled.contains(&kind)
+    }
+}
+
+/// Parses and lints a program
+pub fn lint(lines: &[&str], options: &LintOptions) -> Result<Vec<Warning>, ParseError> {
+    let program = parse_program(lines)?;
+    Ok(lint_program(lines, &program, options))
+}
+
+/// Lints an already parsed program, `lines` must be the source of `program`
+///
+/// The result includes `program.warnings`
+///
+/// Lints can be disabled for a line with `# lint: allow(name, ...)` (or `allow(all)`) either
+/// on the line itself or on the comment lines directly before it
+pub fn lint_program(lines: &[&str], program: &Program, options: &LintOptions) -> Vec<Warning> {
+    let allowed = allowed_warnings(lines);
+    let mut warnings = program.warnings.clone();
+    word_op_byte_values(lines, program, &mut warnings);
+    flags_writes(lines, program, &mut warnings);
+    mid_instruction_jumps(lines, program, &mut warnings);
+    warnings.extend(dead_code(lines, program));
+    warnings.extend(analyze_stack(lines, program).warnings);
+    warnings.retain(|warning| {
+        options.is_enabled(warning.kind)
+            && !allowed
+                .get(warning.line_num)
+                .map(|kinds| kinds.contains(&warning.kind))
+                .unwrap_or_default()
+    });
+    warnings.sort_by_key(|warning| (warning.line_num, warning.span.start));
+    warnings
+}
+
+/// Returns the uppercase op name and args for instructions (not directives)
+fn instruction(line: &ParsedLine) -> Option<(String, &[String])> {
+    let (op, args) = line.line.command.as_ref()?;
+    if op.starts_with('.') {
+        None
+    } else {
+        Some((op.to_ascii_uppercase(), args))
+    }
+}
+
+fn word_op_byte_values(lines: &[&str], program: &Program, warnings: &mut Vec<Warning>) {
+    for line in &program.lines {
+        if let Some((op, args)) = instruction(line) {
+            let byte_op = match op.strip_suffix(".W") {
+                Some(name) => format!("{name}.B"),
+                None => continue,
+            };
+            let writes_memory = line
+                .arguments
+                .iter()
+                .any(|arg| matches!(arg, Argument::Address(_) | Argument::IndirectReg(..)));
+            let has_reg = line
+                .arguments
+                .iter()
+                .any(|arg| matches!(arg, Argument::ExtReg(_)));
+            if !ARG_MATCHES.contains_key(byte_op.as_str()) || !writes_memory || has_reg {
+                continue;
+            }
+            for (idx, (arg, text)) in line.arguments.iter().zip(args).enumerate() {
+                if let Argument::Word(num) = arg {
+                    if *num <= u8::MAX as u16 {
+                        warnings.push(warning(
+                            line.line.num,
+                            arg_span(lines, line.line.num, idx),
+                            WarningKind::WordOpByteValue,
+                            format!("{op} writes a word but {text} fits in a byte, did you mean {byte_op}?"),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn flags_writes(lines: &[&str], program: &Program, warnings: &mut Vec<Warning>) {
+    for line in &program.lines {
+        if let Some((op, args)) = instruction(line) {
+            let parts = if line.expansion.is_empty() {
+                std::slice::from_ref(line)
+            } else {
+                line.expansion.as_slice()
+            };
+            for text in parts.iter().flat_map(written_flags) {
+                //expansion args are copied from the line, so find the original
+                let idx = args
+                    .iter()
+                    .position(|arg| arg.eq_ignore_ascii_case(text))
+                    .unwrap_or(usize::MAX);
+                warnings.push(warning(
+                    line.line.num,
+                    arg_span(lines, line.line.num, idx),
+                    WarningKind::FlagsWrite,
+                    format!("{op} writes to the flags register"),
+                ));
+            }
+        }
+    }
+}
+
+/// Text of the args that are `FLG` and written to
+fn written_flags(line: &ParsedLine) -> Vec<&str> {
+    match instruction(line) {
+        Some((op, args)) => written_args(&op, line.arguments.len())
+            .into_iter()
+            .filter(|idx| {
+                matches!(line.arguments.get(*idx), Some(Argument::Register(reg)) if reg & 0x0F == id::FLAGS)
+            })
+            .filter_map(|idx| args.get(idx).map(String::as_str))
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Indexes of the args `op` writes to
+fn written_args(op: &str, count: usize) -> Vec<usize> {
+    if READ_ONLY_OPS.contains(&op) || op == FLAGS_RESTORE_OP || count == 0 {
+        vec![]
+    } else if SWAP_OPS.contains(&op) {
+        (0..count).collect()
+    } else {
+        vec![0]
+    }
+}
+
+fn mid_instruction_jumps(lines: &[&str], program: &Program, warnings: &mut Vec<Warning>) {
+    for line in &program.lines {
+        if let Some((op, _)) = instruction(line) {
+            if !JUMP_OPS.contains(&op.as_str()) {
+                continue;
+            }
+            if let Some(Argument::Address(target)) = line.arguments.first() {
+                let target = *target as usize;
+                let containing = program.lines.iter().find(|other| {
+                    let start = other.address as usize;
+                    start < target && target < start + other.bytes.len()
+                });
+                if let Some(other) = containing {
+                    warnings.push(warning(
+                        line.line.num,
+                        arg_span(lines, line.line.num, 0),
+                        WarningKind::JumpMidInstruction,
+                        format!(
+                            "{op} target ${target:04X} is in the middle of line {}",
+                            other.line.num
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kinds(lines: &[&str]) -> Vec<(usize, WarningKind)> {
+        lint(lines, &LintOptions::default())
+            .unwrap()
+            .iter()
+            .map(|warning| (warning.line_num, warning.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_word_op_byte_value() {
+        let lines = [
+            "cpy.w $100, 1",
+            "cpy.w ax, 1",
+            "cpy.w $100, 300",
+            "cpy.w (bx), 2",
+        ];
+        assert_eq!(
+            kinds(&lines),
+            vec![
+                (0, WarningKind::WordOpByteValue),
+                (3, WarningKind::WordOpByteValue)
+            ]
+        );
+        let warnings = lint(&lines, &LintOptions::default()).unwrap();
+        assert_eq!(warnings[0].span, 12..13);
+        assert_eq!(
+            warnings[0].to_string(),
+            "CPY.W writes a word but 1 fits in a byte, did you mean CPY.B? on line 0 [word_op_byte_value]"
+        );
+    }
+
+    #[test]
+    fn test_unused_labels() {
+        let lines = [
+            "main: nop",
+            ".loop: jmp .loop",
+            ".unused: nop",
+            "other: call main",
+            "  used: nop",
+            "cpy.w ax, used",
+        ];
+        assert_eq!(
+            kinds(&lines),
+            vec![(2, WarningKind::UnusedLabel), (3, WarningKind::UnusedLabel)]
+        );
+        let warnings = lint(&lines, &LintOptions::default()).unwrap();
+        assert_eq!(warnings[0].span, 0..7);
+        assert_eq!(warnings[0].message, "Label main.unused is never used");
+    }
+
+    #[test]
+    fn test_flags_write() {
+        let lines = [
+            "cpy.b flg, 0",
+            "cmp.b flg, 0",
+            "push.b flg",
+            "pop.b flg",
+            "cpy.b al, flg",
+            "swap.b al, flg",
+            "swap.b flg, al",
+            "clr.b flg",
+            "tst.b flg",
+        ];
+        assert_eq!(
+            kinds(&lines),
+            vec![
+                (0, WarningKind::FlagsWrite),
+                (5, WarningKind::FlagsWrite),
+                (6, WarningKind::FlagsWrite),
+                (7, WarningKind::FlagsWrite)
+            ]
+        );
+        let warnings = lint(&lines, &LintOptions::default()).unwrap();
+        assert_eq!(warnings[1].span, 11..14);
+        assert_eq!(warnings[3].span, 6..9);
+        assert_eq!(warnings[3].message, "CLR.B writes to the flags register");
+    }
+
+    #[test]
+    fn test_mid_instruction_jumps() {
+        let lines = [
+            "nop",
+            "cpy.b al, 1",
+            "je $2",
+            "je $1",
+            "call $8",
+            "jmp $255",
+        ];
+        assert_eq!(
+            kinds(&lines),
+            vec![
+                (2, WarningKind::JumpMidInstruction),
+                (4, WarningKind::JumpMidInstruction)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let lines = [
+            "main: nop",
+            "halt",
+            "inc.b al",
+            "inc.b al",
+            "more: inc.b al",
+            "halt",
+            ".db 1",
+            "other: halt",
+        ];
+        let result: Vec<(usize, WarningKind)> = kinds(&lines)
+            .into_iter()
+            .filter(|(_, kind)| *kind == WarningKind::UnreachableCode)
+            .collect();
+        assert_eq!(result, vec![(2, WarningKind::UnreachableCode)]);
+    }
+
+    #[test]
+    fn test_stack() {
+        let lines = ["main: call func", "halt", "func: push.b al", "ret"];
+        assert_eq!(kinds(&lines), vec![(3, WarningKind::StackImbalance)]);
+    }
+
+    #[test]
+    fn test_options_and_pragmas() {
+        let lines = [
+            "main: cpy.w $100, 1",
+            "cpy.w $102, 1 # lint: allow(word_op_byte_value)",
+            "# lint: allow(all)",
+            "# lint: allow(unknown)",
+            "unused: cpy.b flg, 1",
+            "cpy.b flg, 2 #lint:allow(flags_write, unused_label)",
+            "cpy.b flg, 3 # lint: allow(unused_label)",
+        ];
+        assert_eq!(
+            kinds(&lines),
+            vec![
+                (0, WarningKind::WordOpByteValue),
+                (6, WarningKind::FlagsWrite)
+            ]
+        );
+        let options = LintOptions {
+            disabled: HashSet::from([WarningKind::WordOpByteValue]),
+        };
+        assert_eq!(lint(&lines, &options).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_names() {
+        for kind in WarningKind::ALL {
+            assert_eq!(WarningKind::from_name(kind.name()), Some(kind));
+        }
+        assert_eq!(WarningKind::from_name("missing"), None);
+    }
+}
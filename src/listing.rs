@@ -0,0 +1,74 @@
+use crate::Program;
+
+/// Lists the address, bytes and source of each line, e.g.
+///
+/// ```text
+/// 0000  22 01 01  main: add.b al, 1
+/// 0003  00          nop
+/// 0004              neg.b al
+/// 0004  40 01         = NOT.B al
+/// 0006  2C 01         = INC.B al
+/// ```
+///
+/// Pseudo-ops are followed by their expansion
+pub fn listing(program: &Program) -> String {
+    //(address, bytes, text)
+    let mut rows = vec![];
+    for line in &program.lines {
+        if line.expansion.is_empty() {
+            rows.push((line.address, hex(&line.bytes), line.line.original.clone()));
+        } else {
+            rows.push((line.address, String::new(), line.line.original.clone()));
+            let indent = line.line.original.len() - line.line.original.trim_start().len();
+            for part in &line.expansion {
+                rows.push((
+                    part.address,
+                    hex(&part.bytes),
+                    format!("{:indent$}  = {}", "", part.line.original),
+                ));
+            }
+        }
+    }
+    let bytes_width = rows
+        .iter()
+        .map(|(_, bytes, _)| bytes.len())
+        .max()
+        .unwrap_or_default();
+    let mut output = String::new();
+    for (address, bytes, source) in rows {
+        let text = format!("{address:04X}  {bytes:bytes_width$}  {source}");
+        output.push_str(text.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_program;
+
+    #[test]
+    fn test_listing() {
+        let program = parse_program(&["main: add.b al, 1", "  nop", "  .db 1, 2", "end:"]).unwrap();
+        assert_eq!(
+            listing(&program),
+            "0000  22 01 01  main: add.b al, 1\n0003  00          nop\n0004  01 02       .db 1, 2\n0006            end:\n"
+        );
+    }
+
+    #[test]
+    fn test_pseudo_op_listing() {
+        let program = parse_program(&["main: add.b al, 1", "  nop", "  neg.b al"]).unwrap();
+        let expected = "0000  22 01 01  main: add.b al, 1\n0003  00          nop\n0004              neg.b al\n0004  40 01         = NOT.B al\n0006  2C 01         = INC.B al\n";
+        assert_eq!(listing(&program), expected);
+    }
+}
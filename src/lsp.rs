@@ -0,0 +1,606 @@
+//! Language server for Maikor ASM, using the stdio transport
+//!
+//! Supports diagnostics (from [`ParseError`]s), hover for ops, completion of ops, registers
+//! and labels, and go to definition for labels
+
+use crate::arg_patterns::ARG_MATCHES;
+use crate::forms::op_forms;
+use crate::interpreter::{interpret_line, strip_comment};
+use crate::pseudo_ops::{pseudo_op, PSEUDO_OPS};
+use crate::{lint_program, parse_program, LintOptions, ParseError, Warning};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, LogMessage,
+    Notification as LspNotification, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, Request as LspRequest};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionResponse, Diagnostic,
+    DiagnosticSeverity, GotoDefinitionResponse, Hover, HoverContents, HoverProviderCapability,
+    Location, LogMessageParams, MarkupContent, MarkupKind, MessageType, NumberOrString, OneOf,
+    Position, PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+use maikor_platform::op_desc;
+use maikor_platform::registers::names;
+use std::collections::HashMap;
+use std::error::Error;
+
+type LspResult<T> = Result<T, Box<dyn Error + Sync + Send>>;
+
+/// Runs the server on stdin/stdout until the client disconnects
+pub fn run() -> LspResult<()> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection)?;
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Runs the server on `connection` until the client sends `shutdown`
+pub fn serve(connection: &Connection) -> LspResult<()> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        definition_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    let mut documents: HashMap<Url, String> = HashMap::new();
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+                let response = handle_request(&documents, req);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(not) => {
+                let method = not.method.clone();
+                match handle_notification(&mut documents, not) {
+                    Ok(Some(uri)) => {
+                        let text = documents.get(&uri).map(String::as_str).unwrap_or_default();
+                        let params = PublishDiagnosticsParams::new(uri, diagnostics(text), None);
+                        connection
+                            .sender
+                            .send(Message::Notification(Notification::new(
+                                PublishDiagnostics::METHOD.to_string(),
+                                params,
+                            )))?;
+                    }
+                    Ok(None) => {}
+                    //bad notifications are skipped, as there's no way to reply to them
+                    Err(err) => {
+                        let params = LogMessageParams {
+                            typ: MessageType::ERROR,
+                            message: format!("Invalid {method} notification: {err}"),
+                        };
+                        connection
+                            .sender
+                            .send(Message::Notification(Notification::new(
+                                LogMessage::METHOD.to_string(),
+                                params,
+                            )))?;
+                    }
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Updates `documents`, returning the uri of the changed document (if any)
+fn handle_notification(
+    documents: &mut HashMap<Url, String>,
+    not: Notification,
+) -> LspResult<Option<Url>> {
+    match not.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: <DidOpenTextDocument as LspNotification>::Params =
+                serde_json::from_value(not.params)?;
+            let uri = params.text_document.uri;
+            documents.insert(uri.clone(), params.text_document.text);
+            Ok(Some(uri))
+        }
+        DidChangeTextDocument::METHOD => {
+            let params: <DidChangeTextDocument as LspNotification>::Params =
+                serde_json::from_value(not.params)?;
+            let uri = params.text_document.uri;
+            //full sync, so the last change is the whole document
+            if let Some(change) = params.content_changes.into_iter().last() {
+                documents.insert(uri.clone(), change.text);
+            }
+            Ok(Some(uri))
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: <DidCloseTextDocument as LspNotification>::Params =
+                serde_json::from_value(not.params)?;
+            let uri = params.text_document.uri;
+            documents.remove(&uri);
+            Ok(Some(uri))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn handle_request(documents: &HashMap<Url, String>, req: Request) -> Response {
+    let document = |uri: &Url| documents.get(uri).map(String::as_str).unwrap_or_default();
+    match req.method.as_str() {
+        HoverRequest::METHOD => respond::<HoverRequest>(req, |params| {
+            let params = params.text_document_position_params;
+            hover(document(&params.text_document.uri), params.position)
+        }),
+        Completion::METHOD => respond::<Completion>(req, |params| {
+            let text = document(&params.text_document_position.text_document.uri);
+            Some(CompletionResponse::Array(completions(text)))
+        }),
+        GotoDefinition::METHOD => respond::<GotoDefinition>(req, |params| {
+            let params = params.text_document_position_params;
+            let uri = params.text_document.uri;
+            definition(document(&uri), params.position)
+                .map(|range| GotoDefinitionResponse::Scalar(Location::new(uri, range)))
+        }),
+        _ => Response::new_err(
+            req.id,
+            ErrorCode::MethodNotFound as i32,
+            format!("unsupported request: {}", req.method),
+        ),
+    }
+}
+
+fn respond<R: LspRequest>(req: Request, handler: impl FnOnce(R::Params) -> R::Result) -> Response {
+    let id: RequestId = req.id;
+    match serde_json::from_value::<R::Params>(req.params) {
+        Ok(params) => Response::new_ok(id, handler(params)),
+        Err(err) => Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string()),
+    }
+}
+
+/// Parses the document and converts any error or lint warnings into diagnostics
+pub fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = text.lines().collect();
+    match parse_program(&lines) {
+        Ok(program) => lint_program(&lines, &program, &LintOptions::default())
+            .iter()
+            .map(|warning| warning_to_diagnostic(&lines, warning))
+            .collect(),
+        Err(err) => vec![error_to_diagnostic(&lines, &err)],
+    }
+}
+
+fn warning_to_diagnostic(lines: &[&str], warning: &Warning) -> Diagnostic {
+    let line = lines.get(warning.line_num).copied().unwrap_or_default();
+    Diagnostic {
+        range: Range::new(
+            position(warning.line_num, line, warning.span.start),
+            position(warning.line_num, line, warning.span.end),
+        ),
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String(warning.kind.name().to_string())),
+        source: Some(String::from("maikor-asm")),
+        message: warning.message.clone(),
+        ..Diagnostic::default()
+    }
+}
+
+/// The range covers the contents of the line the error is on (excluding comments)
+fn error_to_diagnostic(lines: &[&str], err: &ParseError) -> Diagnostic {
+    let range = match err.line_num() {
+        Some(line_num) => {
+            let line = lines.get(line_num).copied().unwrap_or_default();
+            let start = line.len() - line.trim_start().len();
+            let end = start + strip_comment(line).len();
+            Range::new(
+                position(line_num, line, start),
+                position(line_num, line, end),
+            )
+        }
+        None => Range::default(),
+    };
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String(err.code().to_string())),
+        source: Some(String::from("maikor-asm")),
+        message: err.to_string(),
+        ..Diagnostic::default()
+    }
+}
+
+/// Shows the forms for the op under the cursor
+pub fn hover(text: &str, position: Position) -> Option<Hover> {
+    let (word, range) = word_at(text, position)?;
+    let op_name = word.to_ascii_uppercase();
+    if let Some(pseudo) = pseudo_op(&op_name) {
+        let mut value = format!("**{op_name}** (pseudo-op)\n\n{}\n", pseudo.description);
+        for form in pseudo.forms() {
+            value.push_str(&format!("\n- `{form}`"));
+        }
+        value.push_str(&format!("\n\nExpands to `{}`", pseudo.expansion.join("; ")));
+        return Some(markdown(value, range));
+    }
+    let forms = op_forms(&op_name)?;
+    let mut value = format!("**{op_name}**\n");
+    for form in forms {
+        let desc = op_desc(form.op_code).unwrap_or_default();
+        let size = if form.min_size() == form.max_size() {
+            form.min_size().to_string()
+        } else {
+            format!("{}-{}", form.min_size(), form.max_size())
+        };
+        value.push_str(&format!(
+            "\n- `{form}` (`0x{:02X}`, {size} bytes) {desc}",
+            form.op_code
+        ));
+    }
+    Some(markdown(value, range))
+}
+
+fn markdown(value: String, range: Range) -> Hover {
+    Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(range),
+    }
+}
+
+/// Returns all op names, register names and labels in the document
+pub fn completions(text: &str) -> Vec<CompletionItem> {
+    let item = |label: &str, kind: CompletionItemKind| CompletionItem {
+        label: label.to_string(),
+        kind: Some(kind),
+        ..CompletionItem::default()
+    };
+    let mut ops: Vec<&&str> = ARG_MATCHES
+        .keys()
+        .chain(PSEUDO_OPS.iter().map(|op| &op.name))
+        .collect();
+    ops.sort();
+    let mut items: Vec<CompletionItem> = ops
+        .into_iter()
+        .map(|op| item(op, CompletionItemKind::KEYWORD))
+        .collect();
+    items.extend(
+        names::ALL
+            .iter()
+            .map(|reg| item(reg, CompletionItemKind::VARIABLE)),
+    );
+    items.extend(
+        label_definitions(text)
+            .iter()
+            .map(|def| item(&def.label, CompletionItemKind::REFERENCE)),
+    );
+    items
+}
+
+/// Returns the range of the definition of the label under the cursor
+pub fn definition(text: &str, position: Position) -> Option<Range> {
+    let (word, _) = word_at(text, position)?;
+    let line_num = position.line as usize;
+    let definitions = label_definitions(text);
+    let full_name = if word.starts_with('.') {
+        let scope = definitions
+            .iter()
+            .rev()
+            .find(|def| def.line_num <= line_num && !def.label.starts_with('.'))?;
+        format!("{}{word}", scope.label)
+    } else {
+        word
+    };
+    let def = definitions.iter().find(|def| def.full_name == full_name)?;
+    let line = text.lines().nth(def.line_num)?;
+    let start = line.find(&def.label)?;
+    Some(Range::new(
+        self::position(def.line_num, line, start),
+        self::position(def.line_num, line, start + def.label.len()),
+    ))
+}
+
+struct LabelDefinition {
+    line_num: usize,
+    /// As written, e.g. `.loop`
+    label: String,
+    /// Including scope, e.g. `main.loop`
+    full_name: String,
+}
+
+/// Finds labels textually, so this works even if the document doesn't parse
+fn label_definitions(text: &str) -> Vec<LabelDefinition> {
+    let mut scope: Option<String> = None;
+    let mut definitions = vec![];
+    for (line_num, line) in text.lines().enumerate() {
+        let label = match interpret_line(line_num, strip_comment(line)) {
+            Ok(line) => line.label,
+            Err(_) => None,
+        };
+        let label = match label {
+            Some(label) if !label.is_empty() && !label.starts_with(['@', '+', '-']) => label,
+            _ => continue,
+        };
+        let full_name = if label.starts_with('.') {
+            match &scope {
+                Some(scope) => format!("{scope}{label}"),
+                None => continue,
+            }
+        } else {
+            scope = Some(label.clone());
+            label.clone()
+        };
+        definitions.push(LabelDefinition {
+            line_num,
+            label,
+            full_name,
+        });
+    }
+    definitions
+}
+
+/// Returns the op/register/label/etc under the cursor
+fn word_at(text: &str, position: Position) -> Option<(String, Range)> {
+    let line_num = position.line as usize;
+    let line = text.lines().nth(line_num)?;
+    let cursor = byte_index(line, position.character);
+    let is_word_char = |chr: char| chr.is_ascii_alphanumeric() || matches!(chr, '.' | '_' | '@');
+    let start = line[..cursor]
+        .char_indices()
+        .rev()
+        .take_while(|(_, chr)| is_word_char(*chr))
+        .last()
+        .map(|(idx, _)| idx)
+        .unwrap_or(cursor);
+    let end = line[cursor..]
+        .char_indices()
+        .find(|(_, chr)| !is_word_char(*chr))
+        .map(|(idx, _)| cursor + idx)
+        .unwrap_or(line.len());
+    let word = line[start..end].trim_end_matches(':');
+    if word.is_empty() {
+        return None;
+    }
+    Some((
+        word.to_string(),
+        Range::new(
+            self::position(line_num, line, start),
+            self::position(line_num, line, start + word.len()),
+        ),
+    ))
+}
+
+/// LSP positions use UTF-16 columns
+fn position(line_num: usize, line: &str, byte_idx: usize) -> Position {
+    let column = line[..byte_idx].encode_utf16().count();
+    Position::new(line_num as u32, column as u32)
+}
+
+fn byte_index(line: &str, column: u32) -> usize {
+    let mut utf16 = 0;
+    for (idx, chr) in line.char_indices() {
+        if utf16 >= column as usize {
+            return idx;
+        }
+        utf16 += chr.len_utf16();
+    }
+    line.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lsp_types::request::{Initialize, Shutdown};
+    use lsp_types::{
+        DidOpenTextDocumentParams, InitializeParams, TextDocumentIdentifier, TextDocumentItem,
+        TextDocumentPositionParams,
+    };
+    use serde_json::Value;
+    use std::thread;
+
+    const PROGRAM: &str =
+        "main: cpy.b al, 1 # start\n.loop: inc.b al\n  jmp .loop\nother: jmp main # lint: allow(unused_label)";
+
+    #[test]
+    fn test_diagnostics() {
+        assert!(diagnostics(PROGRAM).is_empty());
+        let result = diagnostics("main: nop\nunused: cpy.b flg, 1");
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            result[1].range,
+            Range::new(Position::new(1, 14), Position::new(1, 17))
+        );
+        assert!(diagnostics("").is_empty());
+
+        let result = diagnostics("nop\n  cpy.b al, ax  # bad\nnop");
+        assert_eq!(result.len(), 1);
+        assert_eq!(
+            result[0].range,
+            Range::new(Position::new(1, 2), Position::new(1, 14))
+        );
+        assert_eq!(result[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            result[0].code,
+            Some(NumberOrString::String(String::from("E0013")))
+        );
+
+        let result = diagnostics("jmp missing");
+        assert_eq!(
+            result[0].range,
+            Range::new(Position::new(0, 0), Position::new(0, 11))
+        );
+    }
+
+    #[test]
+    fn test_hover() {
+        let result = hover(PROGRAM, Position::new(0, 8)).unwrap();
+        assert_eq!(
+            result.range,
+            Some(Range::new(Position::new(0, 6), Position::new(0, 11)))
+        );
+        match result.contents {
+            HoverContents::Markup(content) => {
+                assert!(content.value.starts_with("**CPY.B**"));
+                assert!(content.value.contains("`CPY.B reg8, imm8`"));
+            }
+            _ => panic!("unexpected hover contents"),
+        }
+        assert!(hover(PROGRAM, Position::new(0, 13)).is_none());
+        assert!(hover(PROGRAM, Position::new(0, 30)).is_none());
+        assert!(hover(PROGRAM, Position::new(9, 0)).is_none());
+        match hover("  clr.b al", Position::new(0, 3)).unwrap().contents {
+            HoverContents::Markup(content) => {
+                assert!(content.value.starts_with("**CLR.B** (pseudo-op)"));
+                assert!(content.value.contains("`CLR.B reg8`"));
+                assert!(content.value.ends_with("Expands to `CPY.B {0}, 0`"));
+            }
+            _ => panic!("unexpected hover contents"),
+        }
+    }
+
+    #[test]
+    fn test_completions() {
+        let items = completions(PROGRAM);
+        let labels: Vec<&str> = items.iter().map(|item| item.label.as_str()).collect();
+        assert!(labels.contains(&"NOP"));
+        assert!(labels.contains(&"CPY.B"));
+        assert!(labels.contains(&"CLR.B"));
+        assert!(labels.contains(&"AX"));
+        assert!(labels.contains(&"main"));
+        assert!(labels.contains(&".loop"));
+        assert!(labels.contains(&"other"));
+    }
+
+    #[test]
+    fn test_definition() {
+        assert_eq!(
+            definition(PROGRAM, Position::new(2, 8)),
+            Some(Range::new(Position::new(1, 0), Position::new(1, 5)))
+        );
+        assert_eq!(
+            definition(PROGRAM, Position::new(3, 12)),
+            Some(Range::new(Position::new(0, 0), Position::new(0, 4)))
+        );
+        assert_eq!(definition(PROGRAM, Position::new(0, 13)), None);
+    }
+
+    #[test]
+    fn test_positions() {
+        let line = "é: nop";
+        assert_eq!(byte_index(line, 1), 2);
+        assert_eq!(position(0, line, 2), Position::new(0, 1));
+        assert_eq!(byte_index(line, 20), line.len());
+    }
+
+    fn send_request<R: LspRequest>(client: &Connection, id: i32, params: R::Params) -> Value {
+        let req = Request::new(RequestId::from(id), R::METHOD.to_string(), params);
+        client.sender.send(Message::Request(req)).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Response(response) => {
+                assert_eq!(response.id, RequestId::from(id));
+                response.result.unwrap_or_default()
+            }
+            msg => panic!("unexpected message {msg:?}"),
+        }
+    }
+
+    #[test]
+    fn test_server() {
+        let (server, client) = Connection::memory();
+        let server = thread::spawn(move || serve(&server).unwrap());
+
+        let result = send_request::<Initialize>(&client, 1, InitializeParams::default());
+        assert_eq!(result["capabilities"]["hoverProvider"], true);
+        client
+            .sender
+            .send(Message::Notification(Notification::new(
+                "initialized".to_string(),
+                serde_json::json!({}),
+            )))
+            .unwrap();
+
+        client
+            .sender
+            .send(Message::Notification(Notification::new(
+                DidOpenTextDocument::METHOD.to_string(),
+                serde_json::json!({ "textDocument": 1 }),
+            )))
+            .unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Notification(not) => {
+                assert_eq!(not.method, LogMessage::METHOD);
+                let params: LogMessageParams = serde_json::from_value(not.params).unwrap();
+                assert_eq!(params.typ, MessageType::ERROR);
+                assert!(params.message.starts_with("Invalid textDocument/didOpen"));
+            }
+            msg => panic!("unexpected message {msg:?}"),
+        }
+
+        let uri = Url::parse("file:///test.masm").unwrap();
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(
+                uri.clone(),
+                String::from("maikor"),
+                1,
+                String::from("nop\njmp missing"),
+            ),
+        };
+        client
+            .sender
+            .send(Message::Notification(Notification::new(
+                DidOpenTextDocument::METHOD.to_string(),
+                params,
+            )))
+            .unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Notification(not) => {
+                assert_eq!(not.method, PublishDiagnostics::METHOD);
+                let params: PublishDiagnosticsParams = serde_json::from_value(not.params).unwrap();
+                assert_eq!(params.uri, uri);
+                assert_eq!(params.diagnostics.len(), 1);
+                assert_eq!(params.diagnostics[0].range.start, Position::new(1, 0));
+            }
+            msg => panic!("unexpected message {msg:?}"),
+        }
+
+        let position = TextDocumentPositionParams::new(
+            TextDocumentIdentifier::new(uri.clone()),
+            Position::new(1, 1),
+        );
+        let result = send_request::<HoverRequest>(
+            &client,
+            2,
+            lsp_types::HoverParams {
+                text_document_position_params: position,
+                work_done_progress_params: Default::default(),
+            },
+        );
+        assert!(result["contents"]["value"]
+            .as_str()
+            .unwrap()
+            .starts_with("**JMP**"));
+
+        let req = Request::new(RequestId::from(3), "unknown".to_string(), Value::Null);
+        client.sender.send(Message::Request(req)).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Response(response) => {
+                assert_eq!(
+                    response.error.unwrap().code,
+                    ErrorCode::MethodNotFound as i32
+                )
+            }
+            msg => panic!("unexpected message {msg:?}"),
+        }
+
+        send_request::<Shutdown>(&client, 4, ());
+        client
+            .sender
+            .send(Message::Notification(Notification::new(
+                "exit".to_string(),
+                Value::Null,
+            )))
+            .unwrap();
+        server.join().unwrap();
+    }
+}
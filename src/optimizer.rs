@@ -0,0 +1,591 @@
+use crate::arg_matching::{arg_list_to_letters, Argument};
+use crate::arg_patterns::ARG_MATCHES;
+use crate::control_flow::Flow;
+use crate::interpreter::split_comment;
+use crate::parsers::parse_argument;
+use crate::symbols::Symbols;
+use crate::{parse_program_with_options, ParseError, ParseOptions, ParsedLine, Program};
+use maikor_platform::op_params::{
+    IND_OFFSET_NUM, IND_POST_DEC, IND_POST_INC, IND_PRE_DEC, IND_PRE_INC, MASK, REGISTER,
+};
+use maikor_platform::registers::id;
+use std::fmt::{Display, Formatter};
+
+/// Type of change made by `optimize`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum OptimizationKind {
+    /// Copying a register to itself, e.g. `CPY.B al, al`
+    SelfCopy,
+    /// Adding or subtracting 0 from a register, e.g. `ADD.B al, 0`
+    AddZero,
+    /// Pushing and then popping the same register, e.g. `PUSH.W ax` then `POP.W ax`
+    PushPop,
+    /// Incrementing and then decrementing the same register (or the reverse)
+    IncDec,
+    /// Jumping to the next instruction
+    JumpToNext,
+    /// An address that a word register already holds, e.g. `ADD.B $100, 1` after
+    /// `CPY.W bx, 256` becomes `ADD.B (bx), 1`
+    AddressToIndirect,
+    /// An indirect register with a 0 offset, e.g. `(bx + 0)` becomes `(bx)`
+    ZeroOffset,
+}
+
+impl OptimizationKind {
+    pub fn description(&self) -> &'static str {
+        match self {
+            OptimizationKind::SelfCopy => "copies a register to itself",
+            OptimizationKind::AddZero => "adds zero",
+            OptimizationKind::PushPop => "push is immediately popped",
+            OptimizationKind::IncDec => "increment and decrement cancel out",
+            OptimizationKind::JumpToNext => "jumps to the next instruction",
+            OptimizationKind::AddressToIndirect => "register already holds the address",
+            OptimizationKind::ZeroOffset => "offset is zero",
+        }
+    }
+}
+
+/// A change made to a source line
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Optimization {
+    pub line_num: usize,
+    pub kind: OptimizationKind,
+    /// Code before the change (without label or comment)
+    pub original: String,
+    /// Code after the change, None if removed
+    pub replacement: Option<String>,
+    pub bytes_saved: usize,
+}
+
+impl Display for Optimization {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.replacement {
+            Some(replacement) => write!(
+                f,
+                "Replaced `{}` with `{replacement}` on line {} ({}, saves {} bytes)",
+                self.original,
+                self.line_num,
+                self.kind.description(),
+                self.bytes_saved
+            ),
+            None => write!(
+                f,
+                "Removed `{}` on line {} ({})",
+                self.original,
+                self.line_num,
+                self.kind.description()
+            ),
+        }
+    }
+}
+
+/// Output of `optimize`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Optimized {
+    pub program: Program,
+    /// Source after the changes, with the same number of lines as the input so line numbers in
+    /// `program` match the original source
+    pub source: Vec<String>,
+    pub changes: Vec<Optimization>,
+}
+
+impl Optimized {
+    pub fn bytes_saved(&self) -> usize {
+        self.changes.iter().map(|change| change.bytes_saved).sum()
+    }
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct OptimizeOptions {
+    /// If true args are also changed to use smaller encodings of the same op, so flags are
+    /// unaffected (see `OptimizationKind::AddressToIndirect` and `OptimizationKind::ZeroOffset`)
+    ///
+    /// Ops are never swapped as their flags may differ:
+    /// - `ADD.W ax, 1` to `INC.W ax`: CARRY and OVERFLOW
+    /// - `MUL.B` by `2^n` to `ASL.B` by `n`: CARRY (last bit shifted out vs product overflow) and OVERFLOW
+    pub size: bool,
+}
+
+/// Parses a program and removes redundant instructions, see `OptimizationKind`
+///
+/// Flags may differ after optimizing, e.g. `ADD.B al, 0` sets the zero flag
+///
+/// Labels after removed code move, so numeric addresses (e.g. `JMP $20`) may become invalid
+pub fn optimize(lines: &[&str], options: &ParseOptions) -> Result<Optimized, ParseError> {
+    optimize_with_options(lines, options, &OptimizeOptions::default())
+}
+
+pub fn optimize_with_options(
+    lines: &[&str],
+    parse_options: &ParseOptions,
+    options: &OptimizeOptions,
+) -> Result<Optimized, ParseError> {
+    let mut source: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+    let mut changes = vec![];
+    loop {
+        let refs: Vec<&str> = source.iter().map(String::as_str).collect();
+        let program = parse_program_with_options(&refs, parse_options)?;
+        let mut found = find_optimizations(&refs, &program);
+        if options.size {
+            for change in find_size_optimizations(&refs, &program) {
+                if !found.iter().any(|other| other.line_num == change.line_num) {
+                    found.push(change);
+                }
+            }
+            found.sort_by_key(|change| change.line_num);
+        }
+        if found.is_empty() {
+            return Ok(Optimized {
+                program,
+                source,
+                changes,
+            });
+        }
+        for change in &found {
+            source[change.line_num] = rewrite_line(
+                &source[change.line_num],
+                program_line(&program, change.line_num),
+                change.replacement.as_deref(),
+            );
+        }
+        changes.extend(found);
+    }
+}
+
+fn program_line(program: &Program, line_num: usize) -> Option<&ParsedLine> {
+    program.lines.iter().find(|line| line.line.num == line_num)
+}
+
+/// Replaces the code in a line, keeping any indentation, label and comment
+pub(crate) fn rewrite_line(
+    source: &str,
+    line: Option<&ParsedLine>,
+    replacement: Option<&str>,
+) -> String {
+    let indent = &source[..source.len() - source.trim_start().len()];
+    let (code, comment) = split_comment(source);
+    let label = match line.and_then(|line| line.line.label.as_ref()) {
+        Some(_) => code.split_once(':').map(|(label, _)| label.trim()),
+        None => None,
+    };
+    let mut output = String::new();
+    if let Some(label) = label {
+        output.push_str(label);
+        output.push(':');
+    }
+    if let Some(replacement) = replacement {
+        if !output.is_empty() {
+            output.push(' ');
+        }
+        output.push_str(replacement);
+    }
+    if let Some(comment) = comment {
+        if !output.is_empty() {
+            output.push(' ');
+        }
+        output.push_str(comment);
+    }
+    if output.is_empty() {
+        output
+    } else {
+        format!("{indent}{output}")
+    }
+}
+
+/// Returns the uppercase op name for instructions (not directives)
+fn instruction(line: &ParsedLine) -> Option<String> {
+    let (op, _) = line.line.command.as_ref()?;
+    if op.starts_with('.') {
+        None
+    } else {
+        Some(op.to_ascii_uppercase())
+    }
+}
+
+fn code(lines: &[&str], line: &ParsedLine) -> String {
+    let text = lines.get(line.line.num).copied().unwrap_or_default();
+    let code = split_comment(text).0;
+    match &line.line.label {
+        Some(_) => code
+            .split_once(':')
+            .map(|(_, code)| code.trim())
+            .unwrap_or(code)
+            .to_string(),
+        None => code.to_string(),
+    }
+}
+
+fn removed(lines: &[&str], line: &ParsedLine, kind: OptimizationKind) -> Optimization {
+    Optimization {
+        line_num: line.line.num,
+        kind,
+        original: code(lines, line),
+        replacement: None,
+        bytes_saved: line.bytes.len(),
+    }
+}
+
+fn find_optimizations(lines: &[&str], program: &Program) -> Vec<Optimization> {
+    let mut changes = vec![];
+    let mut skip_next = false;
+    for (idx, line) in program.lines.iter().enumerate() {
+        if std::mem::take(&mut skip_next) {
+            continue;
+        }
+        let op = match instruction(line) {
+            Some(op) => op,
+            None => continue,
+        };
+        let args = &line.arguments;
+        let kind = match (op.as_str(), args.as_slice()) {
+            ("CPY.B" | "CPY.W", [dst, src]) if dst.is_plain_register() && dst == src => {
+                Some(OptimizationKind::SelfCopy)
+            }
+            (
+                "ADD.B" | "ADD.W" | "SUB.B" | "SUB.W",
+                [dst, Argument::Byte(0) | Argument::Word(0)],
+            ) if dst.is_plain_register() => Some(OptimizationKind::AddZero),
+            ("JMP", [Argument::Address(target)])
+                if *target as usize == line.address as usize + line.bytes.len() =>
+            {
+                Some(OptimizationKind::JumpToNext)
+            }
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            changes.push(removed(lines, line, kind));
+            continue;
+        }
+        //pairs, the second line can't have a label as it may be jumped to
+        let next = match program.lines.get(idx + 1) {
+            Some(next) if next.line.label.is_none() => next,
+            _ => continue,
+        };
+        let next_op = match instruction(next) {
+            Some(op) => op,
+            None => continue,
+        };
+        let same_reg = args.len() == 1 && args[0].is_plain_register() && args == &next.arguments;
+        if !same_reg {
+            continue;
+        }
+        let kind = match (op.as_str(), next_op.as_str()) {
+            ("PUSH.B", "POP.B") | ("PUSH.W", "POP.W") => OptimizationKind::PushPop,
+            ("INC.B", "DEC.B") | ("DEC.B", "INC.B") | ("INC.W", "DEC.W") | ("DEC.W", "INC.W") => {
+                OptimizationKind::IncDec
+            }
+            _ => continue,
+        };
+        changes.push(removed(lines, line, kind));
+        changes.push(removed(lines, next, kind));
+        skip_next = true;
+    }
+    changes
+}
+
+/// Ops that only change registers in their first arg
+const FIRST_ARG_WRITE_OPS: [&str; 17] = [
+    "CPY", "ADD", "SUB", "ADDC", "SUBC", "INC", "DEC", "AND", "OR", "XOR", "NOT", "ASL", "ASR",
+    "LSR", "ROL", "ROR", "CMP",
+];
+
+/// Word register containing a byte register
+fn word_register(reg: u8) -> u8 {
+    if reg < id::FLAGS {
+        id::AX + reg / 2
+    } else {
+        reg
+    }
+}
+
+/// Registers an instruction may change, None if it may change any register
+fn changed_registers(op: &str, args: &[Argument]) -> Option<Vec<u8>> {
+    let name = op.split('.').next().unwrap_or_default();
+    if !FIRST_ARG_WRITE_OPS.contains(&name) {
+        return None;
+    }
+    let mut changed = vec![];
+    for (idx, arg) in args.iter().enumerate() {
+        match arg {
+            Argument::Register(reg) | Argument::ExtReg(reg) if idx == 0 => {
+                changed.push(word_register(reg & 0x0F))
+            }
+            //pre/post increment/decrement
+            Argument::Register(reg) | Argument::ExtReg(reg) if reg & MASK != REGISTER => {
+                changed.push(word_register(reg & 0x0F))
+            }
+            Argument::IndirectReg(reg, ..)
+                if matches!(
+                    reg & MASK,
+                    IND_POST_INC | IND_POST_DEC | IND_PRE_INC | IND_PRE_DEC
+                ) =>
+            {
+                changed.push(word_register(reg & 0x0F))
+            }
+            _ => {}
+        }
+    }
+    Some(changed)
+}
+
+/// Finds args that can use a smaller encoding without changing the op, see `OptimizeOptions.size`
+fn find_size_optimizations(lines: &[&str], program: &Program) -> Vec<Optimization> {
+    let mut changes = vec![];
+    //word registers known to contain a number
+    let mut known: Vec<(u8, u16)> = vec![];
+    for line in &program.lines {
+        if line.line.label.is_some() {
+            known.clear();
+        }
+        let (op, texts) = match (instruction(line), &line.line.command) {
+            (Some(op), Some((_, texts))) => (op, texts),
+            _ => {
+                known.clear();
+                continue;
+            }
+        };
+        let args = &line.arguments;
+        if Flow::of(line) == Flow::Next {
+            let patterns = ARG_MATCHES.get(op.as_str());
+            for (idx, arg) in args.iter().enumerate() {
+                let (kind, reg, saved) = match arg {
+                    Argument::Address(addr) => {
+                        let mut letters: Vec<char> = arg_list_to_letters(args).chars().collect();
+                        letters[idx] = 'I';
+                        let pattern: String = letters.into_iter().collect();
+                        let has_pattern = patterns
+                            .map(|patterns| patterns.contains_key(pattern.as_str()))
+                            .unwrap_or_default();
+                        match known.iter().find(|(_, value)| value == addr) {
+                            Some((reg, _)) if has_pattern && is_literal(&texts[idx]) => {
+                                (OptimizationKind::AddressToIndirect, *reg, 1)
+                            }
+                            _ => continue,
+                        }
+                    }
+                    Argument::IndirectReg(reg, None, Some(0)) if reg & MASK == IND_OFFSET_NUM => {
+                        (OptimizationKind::ZeroOffset, reg & 0x0F, 2)
+                    }
+                    _ => continue,
+                };
+                let name = id::to_name(reg).unwrap_or_default().to_ascii_lowercase();
+                let mut new_args = texts.clone();
+                new_args[idx] = format!("({name})");
+                changes.push(Optimization {
+                    line_num: line.line.num,
+                    kind,
+                    original: code(lines, line),
+                    replacement: Some(format!("{op} {}", new_args.join(", "))),
+                    bytes_saved: saved,
+                });
+                //one change per line, others will be found on the next pass
+                break;
+            }
+        }
+        match (Flow::of(line), op.as_str(), args.as_slice()) {
+            (Flow::Next, "CPY.W", [Argument::ExtReg(reg), Argument::Word(value)])
+                if reg & MASK == REGISTER && is_literal(&texts[1]) =>
+            {
+                known.retain(|(other, _)| other != reg);
+                known.push((*reg, *value));
+            }
+            (Flow::Next, _, _) => match changed_registers(&op, args) {
+                Some(changed) => known.retain(|(reg, _)| !changed.contains(reg)),
+                None => known.clear(),
+            },
+            _ => known.clear(),
+        }
+    }
+    changes
+}
+
+/// Returns true if `text` is a number or address, labels aren't as they move if code changes size
+fn is_literal(text: &str) -> bool {
+    parse_argument(0, text, &Symbols::default()).is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_program;
+
+    fn run(lines: &[&str]) -> Optimized {
+        optimize(lines, &ParseOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_optimize() {
+        let lines = [
+            "main: cpy.b al, al # copy",
+            "  cpy.w ax, ax",
+            "  cpy.b al, ah",
+            "  add.b al, 0",
+            "  sub.w bx, 0",
+            "  add.b $10, 0",
+            "  push.w ax",
+            "  pop.w ax",
+            "  inc.b al",
+            "  dec.b al",
+            "  dec.w ax",
+            ".skip: inc.w ax",
+            "  jmp .next",
+            ".next: halt",
+        ];
+        let optimized = run(&lines);
+        let changes: Vec<(usize, OptimizationKind)> = optimized
+            .changes
+            .iter()
+            .map(|change| (change.line_num, change.kind))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (0, OptimizationKind::SelfCopy),
+                (1, OptimizationKind::SelfCopy),
+                (3, OptimizationKind::AddZero),
+                (4, OptimizationKind::AddZero),
+                (6, OptimizationKind::PushPop),
+                (7, OptimizationKind::PushPop),
+                (8, OptimizationKind::IncDec),
+                (9, OptimizationKind::IncDec),
+                (12, OptimizationKind::JumpToNext),
+            ]
+        );
+        assert_eq!(
+            optimized.changes[0].to_string(),
+            "Removed `cpy.b al, al` on line 0 (copies a register to itself)"
+        );
+        assert_eq!(optimized.source[0], "main: # copy");
+        assert_eq!(optimized.source[1], "");
+        assert_eq!(optimized.source[12], "");
+        assert_eq!(optimized.source[13], ".next: halt");
+        let expected = parse_program(&[
+            "main: cpy.b al, ah",
+            "  add.b $10, 0",
+            "  dec.w ax",
+            ".skip: inc.w ax",
+            ".next: halt",
+        ])
+        .unwrap();
+        assert_eq!(optimized.program.bytes, expected.bytes);
+        let nums: Vec<usize> = optimized
+            .program
+            .lines
+            .iter()
+            .map(|line| line.line.num)
+            .collect();
+        assert_eq!(nums, vec![0, 2, 5, 10, 11, 13]);
+    }
+
+    #[test]
+    fn test_size() {
+        let lines = [
+            "main: cpy.w bx, 256",
+            "  add.b $256, 1",
+            "  cpy.b al, $256 # load",
+            "  cpy.w $256, $256",
+            "  jmp $256",
+            "  cpy.b (cx + 0), 1",
+            "  cpy.w cx, 300",
+            "  inc.b bl",
+            "  add.b $300, 1",
+            "  add.b $256, 1",
+            "other: add.b $300, 1",
+            "  halt",
+        ];
+        let options = OptimizeOptions { size: true };
+        let optimized = optimize_with_options(&lines, &ParseOptions::default(), &options).unwrap();
+        let replacements: Vec<(usize, OptimizationKind, Option<&str>)> = optimized
+            .changes
+            .iter()
+            .map(|change| (change.line_num, change.kind, change.replacement.as_deref()))
+            .collect();
+        assert_eq!(
+            replacements,
+            vec![
+                (
+                    1,
+                    OptimizationKind::AddressToIndirect,
+                    Some("ADD.B (bx), 1")
+                ),
+                (
+                    2,
+                    OptimizationKind::AddressToIndirect,
+                    Some("CPY.B al, (bx)")
+                ),
+                (
+                    3,
+                    OptimizationKind::AddressToIndirect,
+                    Some("CPY.W (bx), $256")
+                ),
+                (5, OptimizationKind::ZeroOffset, Some("CPY.B (cx), 1")),
+                (
+                    8,
+                    OptimizationKind::AddressToIndirect,
+                    Some("ADD.B (cx), 1")
+                ),
+                (
+                    3,
+                    OptimizationKind::AddressToIndirect,
+                    Some("CPY.W (bx), (bx)")
+                ),
+            ]
+        );
+        assert_eq!(optimized.source[2], "  CPY.B al, (bx) # load");
+        assert_eq!(optimized.bytes_saved(), 7);
+        assert_eq!(
+            optimized.changes[3].to_string(),
+            "Replaced `cpy.b (cx + 0), 1` with `CPY.B (cx), 1` on line 5 (offset is zero, saves 2 bytes)"
+        );
+        let original = parse_program(&lines).unwrap();
+        assert_eq!(
+            original.bytes.len() - optimized.program.bytes.len(),
+            optimized.bytes_saved()
+        );
+        //disabled by default
+        assert!(run(&lines).changes.is_empty());
+    }
+
+    #[test]
+    fn test_size_keeps_ops() {
+        //flags may differ from INC.W and ASL.B so these are left alone
+        let lines = ["add.w ax, 1", "mul.b $300, 4", "halt"];
+        let options = OptimizeOptions { size: true };
+        let optimized = optimize_with_options(&lines, &ParseOptions::default(), &options).unwrap();
+        assert!(optimized.changes.is_empty());
+    }
+
+    #[test]
+    fn test_size_keeps_labels() {
+        //data moves to 7 if the add is shortened, so bx would no longer point at it
+        let lines = ["cpy.w bx, 8", "add.b data, 1", "data: .db 0"];
+        let options = OptimizeOptions { size: true };
+        let optimized = optimize_with_options(&lines, &ParseOptions::default(), &options).unwrap();
+        assert!(optimized.changes.is_empty());
+        assert_eq!(
+            optimized.program.bytes,
+            parse_program(&lines).unwrap().bytes
+        );
+        assert_eq!(optimized.program.labels.get("data"), Some(&8));
+
+        let lines = ["data: .db 0", "cpy.w bx, data", "add.b $0, 1"];
+        let optimized = optimize_with_options(&lines, &ParseOptions::default(), &options).unwrap();
+        assert!(optimized.changes.is_empty());
+    }
+
+    #[test]
+    fn test_repeated_passes() {
+        //removing the inc/dec makes the jump go to the next instruction
+        let optimized = run(&["jmp end", "inc.b al", "dec.b al", "end: halt"]);
+        assert_eq!(optimized.changes.len(), 3);
+        assert_eq!(optimized.changes[2].kind, OptimizationKind::JumpToNext);
+        assert_eq!(
+            optimized.program.bytes,
+            parse_program(&["halt"]).unwrap().bytes
+        );
+        //nothing to do
+        let optimized = run(&["cpy.b al, 1", "halt"]);
+        assert!(optimized.changes.is_empty());
+        assert_eq!(optimized.source, vec!["cpy.b al, 1", "halt"]);
+    }
+}
@@ -1,6 +1,9 @@
 use crate::arg_matching::Argument;
-use crate::ParseError;
+use crate::lexer::{tokenize, tokens_text};
+use crate::strings::{encode_char, parse_char_literal};
+use crate::symbols::{is_label_reference, is_valid_symbol_name, Symbols};
 use crate::ParseError::*;
+use crate::{ArgKind, GeneralReason, ParseError, RegisterReason};
 use maikor_platform::op_params::{RegisterPPID, ID, INDIRECT, PP};
 use maikor_platform::registers::id;
 
@@ -35,9 +38,13 @@ impl ArgToken {
     }
 }
 
-pub fn parse_argument(line_num: usize, arg: &str) -> Result<ArgToken, ParseError> {
+pub fn parse_argument(
+    line_num: usize,
+    arg: &str,
+    symbols: &Symbols,
+) -> Result<ArgToken, ParseError> {
     let trimmed = arg.trim_matches(|c: char| c == ',' || c.is_whitespace());
-    return if trimmed.starts_with('$') {
+    if trimmed.starts_with('$') {
         match detect_num(line_num, arg, trimmed.trim_start_matches('$')) {
             Ok(addr) => {
                 if let Some(addr) = addr {
@@ -46,40 +53,51 @@ pub fn parse_argument(line_num: usize, arg: &str) -> Result<ArgToken, ParseError
                     Err(General(
                         line_num,
                         arg.to_string(),
-                        String::from("No address after $"),
+                        GeneralReason::MissingAddress,
                     ))
                 }
             }
             Err(err) => Err(err.num_to_addr()),
         }
     } else {
-        match parse_register(line_num, trimmed) {
+        match parse_register(line_num, trimmed, symbols) {
             Ok(reg) => Ok(reg),
             Err(reg_err) => {
-                if let Some(num) = detect_num(line_num, arg, trimmed)? {
+                if let Some(addr) = symbols.label(line_num, trimmed) {
+                    Ok(ArgToken::Address(addr))
+                } else if is_label_reference(trimmed) {
+                    Err(UnknownSymbol(line_num, trimmed.to_string()))
+                } else if let Some(num) = detect_value(line_num, arg, trimmed, symbols)? {
                     Ok(ArgToken::Number(num))
+                } else if is_valid_symbol_name(trimmed) {
+                    Err(UnknownSymbol(line_num, trimmed.to_string()))
                 } else {
                     Err(reg_err)
                 }
             }
         }
-    };
+    }
 }
 
-fn parse_register(line_num: usize, reg: &str) -> Result<ArgToken, ParseError> {
-    let remaining: String = reg.trim().to_string();
-    let (ppid, remaining) = detect_ppid(&remaining);
+fn parse_register(line_num: usize, reg: &str, symbols: &Symbols) -> Result<ArgToken, ParseError> {
+    let (ppid, remaining) = detect_ppid(reg);
     let (is_indirect, remaining) = detect_indirect(line_num, reg, remaining)?;
-    if let Some((dst, offset)) = remaining.split_once(|c| c == '+') {
+    let tokens = tokenize(remaining);
+    if let Some(idx) = tokens.iter().position(|token| token.is_punct('+')) {
         if ppid.is_some() {
             return Err(InvalidRegister(
                 line_num,
                 reg.to_string(),
-                String::from("Can't use PPID and offset"),
+                RegisterReason::PpidAndOffset,
             ));
         }
-        let dst = detect_register(line_num, reg, dst.trim())?;
-        let offset = detect_offset(line_num, reg, offset.trim())?;
+        let dst = detect_register(line_num, reg, tokens_text(remaining, &tokens[..idx]))?;
+        let offset = detect_offset(
+            line_num,
+            reg,
+            tokens_text(remaining, &tokens[idx + 1..]),
+            symbols,
+        )?;
         let meta: u8 = RegisterPPID::new(
             is_indirect,
             offset.reg.is_some(),
@@ -119,8 +137,13 @@ impl Offset {
     }
 }
 
-fn detect_offset(line_num: usize, original: &str, offset: &str) -> Result<Offset, ParseError> {
-    let num_result = detect_num(line_num, original, offset);
+fn detect_offset(
+    line_num: usize,
+    original: &str,
+    offset: &str,
+    symbols: &Symbols,
+) -> Result<Offset, ParseError> {
+    let num_result = detect_value(line_num, original, offset, symbols);
     let reg_result = detect_register(line_num, original, offset);
     if let Ok(reg) = reg_result {
         if id::size(reg) == 1 {
@@ -137,11 +160,11 @@ fn detect_offset(line_num: usize, original: &str, offset: &str) -> Result<Offset
 
 fn detect_register(line_num: usize, original: &str, remaining: &str) -> Result<u8, ParseError> {
     match id::from_name(&remaining.to_ascii_uppercase()) {
-        Ok(id) => Ok(id as u8),
-        Err(err) => Err(InvalidRegister(
+        Ok(id) => Ok(id),
+        Err(_) => Err(InvalidRegister(
             line_num,
             original.to_string(),
-            err.to_string(),
+            RegisterReason::UnknownName(vec![ArgKind::Register, ArgKind::ExtReg]),
         )),
     }
 }
@@ -151,21 +174,41 @@ fn detect_indirect<'a>(
     original: &str,
     remaining: &'a str,
 ) -> Result<(bool, &'a str), ParseError> {
-    if remaining.starts_with('(') {
-        if remaining.ends_with(')') {
-            Ok((
-                true,
-                remaining.trim_matches(|c| c == '(' || c == ')').trim(),
-            ))
-        } else {
-            Err(InvalidRegister(
-                line_num,
-                original.to_string(),
-                String::from("')' at end, as '(' was found at start"),
-            ))
+    let tokens = tokenize(remaining);
+    match tokens.as_slice() {
+        [first, inner @ .., last] if first.is_punct('(') && last.is_punct(')') => {
+            Ok((true, tokens_text(remaining, inner)))
+        }
+        [first, ..] if first.is_punct('(') => Err(InvalidRegister(
+            line_num,
+            original.to_string(),
+            RegisterReason::Unclosed,
+        )),
+        _ => Ok((false, remaining)),
+    }
+}
+
+/// Numbers can be literals, a constant (from a struct, enum, etc) or a label
+///
+/// Char literals are converted using the charmap, if there is one
+pub fn detect_value(
+    line_num: usize,
+    original: &str,
+    remaining: &str,
+    symbols: &Symbols,
+) -> Result<Option<u16>, ParseError> {
+    if let Some(charmap) = symbols.charmap() {
+        if let Some(chr) = parse_char_literal(remaining) {
+            let value = encode_char(line_num, remaining, chr, Some(charmap))?;
+            return Ok(Some(value as u16));
         }
-    } else {
-        Ok((false, remaining))
+    }
+    match symbols
+        .constant(remaining)
+        .or_else(|| symbols.label(line_num, remaining))
+    {
+        Some(value) => Ok(Some(value)),
+        None => detect_num(line_num, original, remaining),
     }
 }
 
@@ -190,11 +233,7 @@ fn detect_num(line_num: usize, original: &str, remaining: &str) -> Result<Option
                     Err(NumberTooBig(line_num, original.to_string()))
                 }
             }
-            Err(err) => Err(NumberHexFormat(
-                line_num,
-                original.to_string(),
-                err.to_string(),
-            )),
+            Err(err) => Err(NumberHexFormat(line_num, original.to_string(), err)),
         }
     } else if remaining.starts_with('b') {
         match usize::from_str_radix(remaining.trim_start_matches('b'), 2) {
@@ -205,11 +244,7 @@ fn detect_num(line_num: usize, original: &str, remaining: &str) -> Result<Option
                     Err(NumberTooBig(line_num, original.to_string()))
                 }
             }
-            Err(err) => Err(NumberFormat(
-                line_num,
-                original.to_string(),
-                err.to_string(),
-            )),
+            Err(err) => Err(NumberFormat(line_num, original.to_string(), err)),
         }
     } else if remaining.starts_with('-') {
         match remaining.parse::<isize>() {
@@ -220,13 +255,9 @@ fn detect_num(line_num: usize, original: &str, remaining: &str) -> Result<Option
                     Err(SignedNumberNumRange(line_num, original.to_string()))
                 }
             }
-            Err(err) => Err(SignedNumberNumFormat(
-                line_num,
-                original.to_string(),
-                err.to_string(),
-            )),
+            Err(err) => Err(SignedNumberNumFormat(line_num, original.to_string(), err)),
         }
-    } else if remaining.chars().all(|c| c.is_digit(10)) {
+    } else if remaining.chars().all(|c| c.is_ascii_digit()) {
         match remaining.parse::<usize>() {
             Ok(num) => {
                 if num <= u16::MAX as usize {
@@ -235,11 +266,7 @@ fn detect_num(line_num: usize, original: &str, remaining: &str) -> Result<Option
                     Err(NumberTooBig(line_num, original.to_string()))
                 }
             }
-            Err(err) => Err(NumberFormat(
-                line_num,
-                original.to_string(),
-                err.to_string(),
-            )),
+            Err(err) => Err(NumberFormat(line_num, original.to_string(), err)),
         }
     } else {
         Ok(None)
@@ -247,17 +274,15 @@ fn detect_num(line_num: usize, original: &str, remaining: &str) -> Result<Option
 }
 
 fn detect_ppid(reg: &str) -> (Option<(PP, ID)>, &str) {
-    if reg.starts_with('-') {
-        return (Some((PP::Pre, ID::Dec)), reg.trim_start_matches('-').trim());
-    } else if reg.starts_with('+') {
-        return (Some((PP::Pre, ID::Inc)), reg.trim_start_matches('+').trim());
-    } else if reg.ends_with('-') {
-        return (Some((PP::Post, ID::Dec)), reg.trim_end_matches('-').trim());
-    } else if reg.ends_with('+') {
-        return (Some((PP::Post, ID::Inc)), reg.trim_end_matches('+').trim());
-    } else {
-        (None, reg)
-    }
+    let tokens = tokenize(reg);
+    let (ppid, rest) = match tokens.as_slice() {
+        [first, rest @ ..] if first.is_punct('-') => (Some((PP::Pre, ID::Dec)), rest),
+        [first, rest @ ..] if first.is_punct('+') => (Some((PP::Pre, ID::Inc)), rest),
+        [rest @ .., last] if last.is_punct('-') => (Some((PP::Post, ID::Dec)), rest),
+        [rest @ .., last] if last.is_punct('+') => (Some((PP::Post, ID::Inc)), rest),
+        rest => (None, rest),
+    };
+    (ppid, tokens_text(reg, rest))
 }
 
 #[cfg(test)]
@@ -327,74 +352,95 @@ mod test {
 
     #[test]
     fn test_parse_argument() {
-        assert_eq!(parse_argument(0, "605").unwrap(), Number(605));
-        assert_eq!(parse_argument(0, "xF11").unwrap(), Number(3857));
-        assert_eq!(parse_argument(0, "$100").unwrap(), Address(100));
-        assert_eq!(parse_argument(0, "$xF").unwrap(), Address(15));
-        assert_eq!(parse_argument(0, "aL").unwrap(), Register(1, None, None));
         assert_eq!(
-            parse_argument(0, "(Bx)").unwrap(),
+            parse_argument(0, "605", &Symbols::default()).unwrap(),
+            Number(605)
+        );
+        assert_eq!(
+            parse_argument(0, "xF11", &Symbols::default()).unwrap(),
+            Number(3857)
+        );
+        assert_eq!(
+            parse_argument(0, "$100", &Symbols::default()).unwrap(),
+            Address(100)
+        );
+        assert_eq!(
+            parse_argument(0, "$xF", &Symbols::default()).unwrap(),
+            Address(15)
+        );
+        assert_eq!(
+            parse_argument(0, "aL", &Symbols::default()).unwrap(),
+            Register(1, None, None)
+        );
+        assert_eq!(
+            parse_argument(0, "(Bx)", &Symbols::default()).unwrap(),
             Register(10 | INDIRECT, None, None)
         );
         assert_eq!(
-            parse_argument(0, "-ch").unwrap(),
+            parse_argument(0, "-ch", &Symbols::default()).unwrap(),
             Register(4 | PRE_DEC, None, None)
         );
         assert_eq!(
-            parse_argument(0, "(dx)+").unwrap(),
+            parse_argument(0, "(dx)+", &Symbols::default()).unwrap(),
             Register(12 | IND_POST_INC, None, None)
         );
         assert_eq!(
-            parse_argument(0, "(ax+563)").unwrap(),
+            parse_argument(0, "(ax+563)", &Symbols::default()).unwrap(),
             Register(9 | IND_OFFSET_NUM, None, Some(563))
         );
         assert_eq!(
-            parse_argument(0, "(ax+dh)").unwrap(),
+            parse_argument(0, "(ax+dh)", &Symbols::default()).unwrap(),
             Register(9 | IND_OFFSET_REG, Some(6), None)
         );
         assert_eq!(
-            parse_argument(0, "(ax+bx)").unwrap(),
+            parse_argument(0, "(ax+bx)", &Symbols::default()).unwrap(),
             Register(9 | IND_OFFSET_EXT_REG, Some(10), None)
         );
 
-        assert!(parse_argument(0, "a").is_err());
-        assert!(parse_argument(0, "78021").is_err());
-        assert!(parse_argument(0, "xFFFF1").is_err());
-        assert!(parse_argument(0, "$121231").is_err());
-        assert!(parse_argument(0, "(dx").is_err());
-        assert!(parse_argument(0, "(dx+141351)").is_err());
-        assert!(parse_argument(0, "(dx+a)").is_err());
-        assert!(parse_argument(0, "(-dx+a)").is_err());
-        assert!(parse_argument(0, "((dx)+al)").is_err());
-        assert!(parse_argument(0, "(dx+10)-").is_err());
+        assert!(parse_argument(0, "a", &Symbols::default()).is_err());
+        assert!(parse_argument(0, "78021", &Symbols::default()).is_err());
+        assert!(parse_argument(0, "xFFFF1", &Symbols::default()).is_err());
+        assert!(parse_argument(0, "$121231", &Symbols::default()).is_err());
+        assert!(parse_argument(0, "(dx", &Symbols::default()).is_err());
+        assert!(parse_argument(0, "(dx+141351)", &Symbols::default()).is_err());
+        assert!(parse_argument(0, "(dx+a)", &Symbols::default()).is_err());
+        assert!(parse_argument(0, "(-dx+a)", &Symbols::default()).is_err());
+        assert!(parse_argument(0, "((dx)+al)", &Symbols::default()).is_err());
+        assert!(parse_argument(0, "(dx+10)-", &Symbols::default()).is_err());
     }
 
     #[test]
     fn test_register() {
-        assert_eq!(parse_register(0, "AH ").unwrap(), (Register(0, None, None)));
-        assert_eq!(parse_register(0, "AX").unwrap(), (Register(9, None, None)));
         assert_eq!(
-            parse_register(0, "(AX )").unwrap(),
+            parse_register(0, "AH ", &Symbols::default()).unwrap(),
+            (Register(0, None, None))
+        );
+        assert_eq!(
+            parse_register(0, "AX", &Symbols::default()).unwrap(),
+            (Register(9, None, None))
+        );
+        assert_eq!(
+            parse_register(0, "(AX )", &Symbols::default()).unwrap(),
             (Register(9 | INDIRECT, None, None))
         );
         assert_eq!(
-            parse_register(0, "- ( AX)").unwrap(),
+            parse_register(0, "- ( AX)", &Symbols::default()).unwrap(),
             (Register(9 | IND_PRE_DEC, None, None))
         );
         assert_eq!(
-            parse_register(0, "CL +").unwrap(),
+            parse_register(0, "CL +", &Symbols::default()).unwrap(),
             (Register(5 | POST_INC, None, None))
         );
         assert_eq!(
-            parse_register(0, "( DX + 10)").unwrap(),
+            parse_register(0, "( DX + 10)", &Symbols::default()).unwrap(),
             (Register(12 | IND_OFFSET_NUM, None, Some(10)))
         );
         assert_eq!(
-            parse_register(0, "(DX + BH )").unwrap(),
+            parse_register(0, "(DX + BH )", &Symbols::default()).unwrap(),
             (Register(12 | IND_OFFSET_REG, Some(2), None))
         );
         assert_eq!(
-            parse_register(0, "( CX + AX)").unwrap(),
+            parse_register(0, "( CX + AX)", &Symbols::default()).unwrap(),
             (Register(11 | IND_OFFSET_EXT_REG, Some(9), None))
         );
     }
@@ -464,46 +510,46 @@ mod test {
     #[test]
     fn test_offset_detection() {
         assert_eq!(
-            detect_offset(0, "", "100").unwrap(),
+            detect_offset(0, "", "100", &Symbols::default()).unwrap(),
             Offset {
                 num: Some(100),
                 ..Offset::default()
             }
         );
         assert_eq!(
-            detect_offset(0, "", "x100").unwrap(),
+            detect_offset(0, "", "x100", &Symbols::default()).unwrap(),
             Offset {
                 num: Some(256),
                 ..Offset::default()
             }
         );
         assert_eq!(
-            detect_offset(0, "", "-124").unwrap(),
+            detect_offset(0, "", "-124", &Symbols::default()).unwrap(),
             Offset {
                 num: Some(65412),
                 ..Offset::default()
             }
         );
         assert_eq!(
-            detect_offset(0, "", "bl").unwrap(),
+            detect_offset(0, "", "bl", &Symbols::default()).unwrap(),
             Offset {
                 reg: Some(3),
                 ..Offset::default()
             }
         );
         assert_eq!(
-            detect_offset(0, "", "dx").unwrap(),
+            detect_offset(0, "", "dx", &Symbols::default()).unwrap(),
             Offset {
                 ext_reg: Some(12),
                 ..Offset::default()
             }
         );
 
-        assert!(detect_offset(0, "", "(ax)").is_err());
-        assert!(detect_offset(0, "", "90000").is_err());
-        assert!(detect_offset(0, "", "xFFFFF").is_err());
-        assert!(detect_offset(0, "", "-ax").is_err());
-        assert!(detect_offset(0, "", "al+").is_err());
+        assert!(detect_offset(0, "", "(ax)", &Symbols::default()).is_err());
+        assert!(detect_offset(0, "", "90000", &Symbols::default()).is_err());
+        assert!(detect_offset(0, "", "xFFFFF", &Symbols::default()).is_err());
+        assert!(detect_offset(0, "", "-ax", &Symbols::default()).is_err());
+        assert!(detect_offset(0, "", "al+", &Symbols::default()).is_err());
     }
 
     #[test]
@@ -518,4 +564,59 @@ mod test {
         assert_eq!(detect_num(0, "", "'A'").unwrap().unwrap(), 65);
         assert_eq!(detect_num(0, "", "'\''").unwrap().unwrap(), 39);
     }
+
+    #[test]
+    fn test_symbols() {
+        let mut symbols = Symbols::default();
+        symbols.add_constant(0, "Enemy.hp", 2).unwrap();
+        assert_eq!(parse_argument(0, "Enemy.hp", &symbols).unwrap(), Number(2));
+        assert_eq!(
+            parse_argument(0, "(bx + Enemy.hp)", &symbols).unwrap(),
+            Register(10 | IND_OFFSET_NUM, None, Some(2))
+        );
+        assert_eq!(
+            detect_offset(0, "", "Enemy.hp", &symbols).unwrap(),
+            Offset::new(None, None, Some(2))
+        );
+        assert!(matches!(
+            parse_argument(0, "main", &Symbols::default()),
+            Err(UnknownSymbol(0, _))
+        ));
+        assert!(matches!(
+            parse_argument(0, "-", &Symbols::default()),
+            Err(UnknownSymbol(0, _))
+        ));
+    }
+
+    #[test]
+    fn test_char_offsets() {
+        assert_eq!(
+            parse_argument(0, "(ax + '+')", &Symbols::default()).unwrap(),
+            Register(9 | IND_OFFSET_NUM, None, Some(b'+' as u16))
+        );
+        assert_eq!(
+            parse_argument(0, "(ax + ')')", &Symbols::default()).unwrap(),
+            Register(9 | IND_OFFSET_NUM, None, Some(b')' as u16))
+        );
+    }
+
+    #[test]
+    fn test_error_reasons() {
+        assert!(matches!(
+            parse_argument(0, "(dx", &Symbols::default()),
+            Err(InvalidRegister(0, _, RegisterReason::Unclosed))
+        ));
+        assert!(matches!(
+            parse_register(0, "-(dx + 1)", &Symbols::default()),
+            Err(InvalidRegister(0, _, RegisterReason::PpidAndOffset))
+        ));
+        assert!(matches!(
+            parse_argument(0, "$zz", &Symbols::default()),
+            Err(General(0, _, GeneralReason::MissingAddress))
+        ));
+        assert_eq!(
+            detect_register(0, "yh", "yh").unwrap_err().to_string(),
+            "Register has invalid format yh on line 0, expected reg8 or reg16"
+        );
+    }
 }
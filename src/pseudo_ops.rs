@@ -0,0 +1,121 @@
+use crate::forms::{describe_forms, pattern_to_args};
+
+/// An op that is expanded into real ops before encoding
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PseudoOp {
+    pub name: &'static str,
+    /// Argument patterns using the `ARG_MATCHES` letters
+    pub patterns: &'static [&'static str],
+    /// Real ops, `{0}` is replaced with the first arg
+    pub expansion: &'static [&'static str],
+    pub description: &'static str,
+}
+
+/// Indirect registers aren't supported when the arg is used more than once, as `(bx)+` would
+/// increment twice
+pub const PSEUDO_OPS: [PseudoOp; 7] = [
+    PseudoOp {
+        name: "CLR.B",
+        patterns: &["R", "I", "A"],
+        expansion: &["CPY.B {0}, 0"],
+        description: "Set byte to 0",
+    },
+    PseudoOp {
+        name: "CLR.W",
+        patterns: &["E", "I", "A"],
+        expansion: &["CPY.W {0}, 0"],
+        description: "Set word to 0",
+    },
+    PseudoOp {
+        name: "TST.B",
+        patterns: &["R", "I"],
+        expansion: &["CMP.B {0}, 0"],
+        description: "Compare byte to 0",
+    },
+    PseudoOp {
+        name: "TST.W",
+        patterns: &["E", "I"],
+        expansion: &["CMP.W {0}, 0"],
+        description: "Compare word to 0",
+    },
+    PseudoOp {
+        name: "NEG.B",
+        patterns: &["R"],
+        expansion: &["NOT.B {0}", "INC.B {0}"],
+        description: "Negate byte (two's complement)",
+    },
+    PseudoOp {
+        name: "NEG.W",
+        patterns: &["E"],
+        expansion: &["NOT.W {0}", "INC.W {0}"],
+        description: "Negate word (two's complement)",
+    },
+    PseudoOp {
+        name: "SWAPB",
+        patterns: &["E"],
+        expansion: &["ROL.W {0}, 8"],
+        description: "Swap high and low bytes of word register",
+    },
+];
+
+/// `op_name` must be uppercase
+pub fn pseudo_op(op_name: &str) -> Option<&'static PseudoOp> {
+    PSEUDO_OPS.iter().find(|op| op.name == op_name)
+}
+
+impl PseudoOp {
+    /// Returns each form, e.g. `CLR.B reg8`
+    pub fn forms(&self) -> Vec<String> {
+        self.patterns
+            .iter()
+            .map(|pattern| describe_forms(self.name, &[pattern_to_args(pattern)]))
+            .collect()
+    }
+
+    /// Returns the real ops with the args filled in
+    pub fn expand(&self, args: &[String]) -> Vec<String> {
+        self.expansion
+            .iter()
+            .map(|template| {
+                args.iter()
+                    .enumerate()
+                    .fold(template.to_string(), |text, (idx, arg)| {
+                        text.replace(&format!("{{{idx}}}"), arg)
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arg_patterns::ARG_MATCHES;
+    use crate::interpreter::interpret_line;
+
+    #[test]
+    fn test_expand() {
+        let op = pseudo_op("NEG.W").unwrap();
+        assert_eq!(
+            op.expand(&[String::from("ax")]),
+            vec!["NOT.W ax", "INC.W ax"]
+        );
+        assert_eq!(
+            pseudo_op("CLR.B").unwrap().forms(),
+            vec!["CLR.B reg8", "CLR.B (reg16)", "CLR.B addr"]
+        );
+        assert!(pseudo_op("CPY.B").is_none());
+    }
+
+    #[test]
+    fn test_expansions_are_real_ops() {
+        for op in PSEUDO_OPS {
+            assert!(!ARG_MATCHES.contains_key(op.name), "{}", op.name);
+            for template in op.expansion {
+                let line = interpret_line(0, template).unwrap();
+                let (name, _) = line.command.unwrap();
+                assert!(ARG_MATCHES.contains_key(name.as_str()), "{template}");
+            }
+        }
+    }
+}
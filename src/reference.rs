@@ -0,0 +1,90 @@
+use crate::arg_patterns::ARG_MATCHES;
+use crate::forms::{op_forms, ArgKind};
+use crate::pseudo_ops::PSEUDO_OPS;
+use maikor_platform::op_desc;
+
+/// Generates a Markdown table of every op, argument form, op code and instruction length
+///
+/// The output is checked in as `docs/instructions.md`
+pub fn instruction_reference() -> String {
+    let mut output = String::from("# Instruction reference\n\n");
+    output.push_str("Generated from the parser, do not edit\n\n");
+    output.push_str("| Argument | Meaning | Size |\n|---|---|---|\n");
+    for (kind, meaning) in [
+        (ArgKind::Register, "byte register, e.g. `al`"),
+        (ArgKind::ExtReg, "word register, e.g. `ax`"),
+        (
+            ArgKind::IndirectReg,
+            "indirect word register, e.g. `(bx)`, `(bx + al)` or `(bx + 12)`",
+        ),
+        (ArgKind::Address, "address, e.g. `$1234` or a label"),
+        (ArgKind::Byte, "byte, e.g. `12`"),
+        (ArgKind::Word, "word, e.g. `1234`"),
+    ] {
+        output.push_str(&format!(
+            "| `{}` | {meaning} | {} |\n",
+            kind.name(),
+            kind.size()
+        ));
+    }
+    output.push_str("\nIndirect registers are followed by 1 byte for a register offset or 2 bytes for a number offset\n\n");
+    output.push_str("| Op | Form | Op code | Description | Length |\n|---|---|---|---|---|\n");
+    let mut op_names: Vec<&&str> = ARG_MATCHES.keys().collect();
+    op_names.sort();
+    for op_name in op_names {
+        for form in op_forms(op_name).unwrap_or_default() {
+            let length = if form.min_size() == form.max_size() {
+                form.min_size().to_string()
+            } else {
+                format!("{}-{}", form.min_size(), form.max_size())
+            };
+            output.push_str(&format!(
+                "| {op_name} | `{form}` | `0x{:02X}` | {} | {length} |\n",
+                form.op_code,
+                op_desc(form.op_code).unwrap_or_default()
+            ));
+        }
+    }
+    output.push_str("\n## Pseudo-ops\n\nExpanded into real ops before encoding, `{0}` is the first argument\n\n");
+    output.push_str("| Pseudo-op | Form | Expands to | Description |\n|---|---|---|---|\n");
+    for op in PSEUDO_OPS {
+        for form in op.forms() {
+            output.push_str(&format!(
+                "| {} | `{form}` | `{}` | {} |\n",
+                op.name,
+                op.expansion.join("; "),
+                op.description
+            ));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_reference_is_current() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("docs/instructions.md");
+        let generated = instruction_reference();
+        if std::env::var_os("UPDATE_REFERENCE").is_some() {
+            fs::write(&path, &generated).unwrap();
+        }
+        let existing = fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            existing == generated,
+            "docs/instructions.md is out of date, run `UPDATE_REFERENCE=1 cargo test reference`"
+        );
+    }
+
+    #[test]
+    fn test_reference() {
+        let reference = instruction_reference();
+        assert!(reference.contains("| ADD.B | `ADD.B reg8, imm8` | `0x22` |"));
+        assert!(reference.contains("| NOP | `NOP` | `0x00` | NOP | 1 |"));
+        assert!(reference.contains("| NEG.B | `NEG.B reg8` | `NOT.B {0}; INC.B {0}` |"));
+    }
+}
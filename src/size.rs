@@ -0,0 +1,70 @@
+use crate::Program;
+use std::ops::Range;
+
+/// Returns the range of lines (in `program.lines`) for a label
+///
+/// For global labels this is up to the next global label, and for local labels
+/// (e.g. `main.loop`) up to the next label
+pub fn label_range(program: &Program, label: &str) -> Option<Range<usize>> {
+    let lines = &program.lines;
+    let start = lines
+        .iter()
+        .position(|line| line.line.label.as_deref() == Some(label))?;
+    let is_local = label.contains('.');
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| match &line.line.label {
+            Some(other) => is_local || !other.starts_with(&format!("{label}.")),
+            None => false,
+        })
+        .map(|idx| start + 1 + idx)
+        .unwrap_or(lines.len());
+    Some(start..end)
+}
+
+/// Size in bytes of a label, see `label_range`
+pub fn label_size(program: &Program, label: &str) -> Option<usize> {
+    label_range(program, label).map(|range| {
+        program.lines[range]
+            .iter()
+            .map(|line| line.bytes.len())
+            .sum()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_program;
+
+    #[test]
+    fn test_label_size() {
+        let program = parse_program(&[
+            "main: add.b al, 1",
+            "  add.w $100, 1",
+            "  add.b (bx), 1",
+            "  add.b (bx + 300), 1",
+            "  push.w ax",
+            "  call main",
+            "  .db 1, 2",
+            "sub:",
+            "  ret",
+            "  neg.b al",
+        ])
+        .unwrap();
+        assert_eq!(label_size(&program, "main"), Some(23));
+        assert_eq!(label_size(&program, "sub"), Some(5));
+        assert_eq!(label_range(&program, "sub"), Some(7..10));
+        assert_eq!(label_range(&program, "missing"), None);
+        assert_eq!(label_size(&program, "missing"), None);
+    }
+
+    #[test]
+    fn test_local_label_range() {
+        let program =
+            parse_program(&["main: nop", ".a: nop", "nop", ".b: nop", "other: nop"]).unwrap();
+        assert_eq!(label_range(&program, "main"), Some(0..4));
+        assert_eq!(label_range(&program, "main.a"), Some(1..3));
+        assert_eq!(label_range(&program, "main.b"), Some(3..4));
+    }
+}
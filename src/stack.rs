@@ -0,0 +1,433 @@
+use crate::control_flow::{ControlFlowGraph, EdgeKind, Flow};
+use crate::warnings::{arg_span, code_span, warning, Warning, WarningKind};
+use crate::{ParsedLine, Program};
+use maikor_platform::mem::sizes;
+use std::collections::HashMap;
+
+/// Bytes pushed by `CALL` for the return address
+const RETURN_ADDRESS_SIZE: usize = 2;
+
+/// Code starting at the entry point or a `CALL` target, and every block reachable from it
+/// without calling
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Routine {
+    /// Index of the first block in `ControlFlowGraph.blocks`
+    pub block: usize,
+    pub label: Option<String>,
+    pub address: u16,
+    /// Indexes of the blocks in the routine, in order
+    pub blocks: Vec<usize>,
+    /// Indexes of the routines called, in `StackAnalysis.routines`
+    pub calls: Vec<usize>,
+    /// True if the routine calls an address not known until runtime, e.g. `CALL ax`
+    pub has_unknown_calls: bool,
+    /// Most bytes pushed by the routine itself, excluding calls
+    pub local_depth: usize,
+    /// Most bytes pushed by the routine and the routines it calls (including return addresses),
+    /// None if the routine is recursive or calls a recursive routine
+    pub max_depth: Option<usize>,
+}
+
+/// Static call graph and worst case stack depth of a program
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StackAnalysis {
+    /// Routine 0 is the entry point (if the program isn't empty)
+    pub routines: Vec<Routine>,
+    /// `recursion`, `stack_imbalance` and `stack_overflow` warnings
+    pub warnings: Vec<Warning>,
+}
+
+#[derive(Debug)]
+struct CallSite {
+    line_num: usize,
+    /// Block of the target, None if not known
+    target: Option<usize>,
+    /// Bytes pushed by the routine before the call
+    depth: isize,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Visit {
+    New,
+    InProgress,
+    Done(Option<usize>),
+}
+
+/// Builds the call graph and finds the worst case stack depth of each routine
+///
+/// Warns about recursion, `RET`s with a different number of bytes pushed and popped, blocks
+/// reached with different stack depths and programs that may use more than the stack size
+pub fn analyze_stack(lines: &[&str], program: &Program) -> StackAnalysis {
+    let graph = ControlFlowGraph::new(program);
+    let mut warnings = vec![];
+    let mut entries = vec![];
+    if !graph.blocks.is_empty() {
+        entries.push(0);
+    }
+    for block in &graph.blocks {
+        for edge in &block.edges {
+            if edge.kind == EdgeKind::Call && !entries.contains(&edge.to) {
+                entries.push(edge.to);
+            }
+        }
+    }
+    let (mut routines, call_sites): (Vec<Routine>, Vec<Vec<CallSite>>) = entries
+        .iter()
+        .map(|entry| walk_routine(lines, program, &graph, *entry, &mut warnings))
+        .unzip();
+    for (routine, sites) in routines.iter_mut().zip(&call_sites) {
+        for site in sites {
+            match site
+                .target
+                .and_then(|block| entries.iter().position(|entry| *entry == block))
+            {
+                Some(callee) if !routine.calls.contains(&callee) => routine.calls.push(callee),
+                Some(_) => {}
+                None => routine.has_unknown_calls = true,
+            }
+        }
+    }
+    let mut visits = vec![Visit::New; routines.len()];
+    let mut path = vec![];
+    for idx in 0..routines.len() {
+        let max_depth = max_depth(
+            lines,
+            idx,
+            &routines,
+            &entries,
+            &call_sites,
+            &mut visits,
+            &mut path,
+            &mut warnings,
+        );
+        routines[idx].max_depth = max_depth;
+    }
+    if let Some(Some(depth)) = routines.first().map(|routine| routine.max_depth) {
+        if depth > sizes::STACK as usize {
+            let line_num = program.lines[graph.blocks[0].lines.start].line.num;
+            warnings.push(warning(
+                line_num,
+                code_span(lines, line_num),
+                WarningKind::StackOverflow,
+                format!(
+                    "Worst case stack depth is {depth} bytes but the stack is {} bytes",
+                    sizes::STACK
+                ),
+            ));
+        }
+    }
+    warnings.sort_by_key(|warning| (warning.line_num, warning.span.start));
+    StackAnalysis { routines, warnings }
+}
+
+/// Bytes pushed (positive) or popped (negative) by a line, excluding `CALL` and `RET`
+fn stack_change(line: &ParsedLine) -> isize {
+    let op = match &line.line.command {
+        Some((op, _)) => op.to_ascii_uppercase(),
+        None => return 0,
+    };
+    match op.as_str() {
+        "PUSH.B" => 1,
+        "PUSH.W" => 2,
+        "POP.B" => -1,
+        "POP.W" => -2,
+        _ => 0,
+    }
+}
+
+fn walk_routine(
+    lines: &[&str],
+    program: &Program,
+    graph: &ControlFlowGraph,
+    entry: usize,
+    warnings: &mut Vec<Warning>,
+) -> (Routine, Vec<CallSite>) {
+    let mut depths: HashMap<usize, isize> = HashMap::new();
+    let mut call_sites = vec![];
+    let mut local_depth = 0;
+    let mut pending = vec![(entry, 0)];
+    while let Some((idx, depth)) = pending.pop() {
+        let block = &graph.blocks[idx];
+        if let Some(existing) = depths.get(&idx) {
+            if *existing != depth {
+                let line_num = program.lines[block.lines.start].line.num;
+                push_once(
+                    warnings,
+                    warning(
+                        line_num,
+                        code_span(lines, line_num),
+                        WarningKind::StackImbalance,
+                        format!(
+                            "Stack has {} or {} bytes pushed here depending on the path",
+                            existing.min(&depth),
+                            existing.max(&depth)
+                        ),
+                    ),
+                );
+            }
+            continue;
+        }
+        depths.insert(idx, depth);
+        let mut depth = depth;
+        for line in &program.lines[block.lines.clone()] {
+            let line_num = line.line.num;
+            match Flow::of(line) {
+                Flow::Call(target) => call_sites.push(CallSite {
+                    line_num,
+                    target: target.and_then(|addr| {
+                        graph.blocks.iter().position(|block| block.address == addr)
+                    }),
+                    depth,
+                }),
+                Flow::Return if depth != 0 => {
+                    let message = if depth > 0 {
+                        format!("Returns with {depth} bytes still pushed")
+                    } else {
+                        format!("Returns after popping {} bytes more than pushed", -depth)
+                    };
+                    push_once(
+                        warnings,
+                        warning(
+                            line_num,
+                            code_span(lines, line_num),
+                            WarningKind::StackImbalance,
+                            message,
+                        ),
+                    );
+                }
+                _ => {}
+            }
+            depth += stack_change(line);
+            local_depth = local_depth.max(depth);
+        }
+        for edge in &block.edges {
+            if edge.kind != EdgeKind::Call {
+                pending.push((edge.to, depth));
+            }
+        }
+    }
+    let mut blocks: Vec<usize> = depths.into_keys().collect();
+    blocks.sort();
+    let block = &graph.blocks[entry];
+    let routine = Routine {
+        block: entry,
+        label: block.label.clone(),
+        address: block.address,
+        blocks,
+        calls: vec![],
+        has_unknown_calls: false,
+        local_depth: local_depth as usize,
+        max_depth: None,
+    };
+    (routine, call_sites)
+}
+
+/// Routines can share blocks (e.g. jumping to another routine), so only report each problem once
+fn push_once(warnings: &mut Vec<Warning>, warning: Warning) {
+    if !warnings.contains(&warning) {
+        warnings.push(warning);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn max_depth(
+    lines: &[&str],
+    idx: usize,
+    routines: &[Routine],
+    entries: &[usize],
+    call_sites: &[Vec<CallSite>],
+    visits: &mut Vec<Visit>,
+    path: &mut Vec<usize>,
+    warnings: &mut Vec<Warning>,
+) -> Option<usize> {
+    match visits[idx] {
+        Visit::Done(depth) => return depth,
+        Visit::InProgress => return None,
+        Visit::New => {}
+    }
+    visits[idx] = Visit::InProgress;
+    path.push(idx);
+    let mut result = Some(routines[idx].local_depth);
+    for site in &call_sites[idx] {
+        let callee = match site
+            .target
+            .and_then(|block| entries.iter().position(|entry| *entry == block))
+        {
+            Some(callee) => callee,
+            None => continue,
+        };
+        if visits[callee] == Visit::InProgress {
+            let start = path.iter().position(|routine| *routine == callee);
+            let names: Vec<String> = path[start.unwrap_or_default()..]
+                .iter()
+                .chain([&callee])
+                .map(|routine| name(&routines[*routine]))
+                .collect();
+            warnings.push(warning(
+                site.line_num,
+                arg_span(lines, site.line_num, 0),
+                WarningKind::Recursion,
+                format!("Recursive call: {}", names.join(" -> ")),
+            ));
+        }
+        let callee_depth = max_depth(
+            lines, callee, routines, entries, call_sites, visits, path, warnings,
+        );
+        result = match (result, callee_depth) {
+            (Some(depth), Some(callee_depth)) => {
+                Some(depth.max(site.depth.max(0) as usize + RETURN_ADDRESS_SIZE + callee_depth))
+            }
+            _ => None,
+        };
+    }
+    path.pop();
+    visits[idx] = Visit::Done(result);
+    result
+}
+
+fn name(routine: &Routine) -> String {
+    routine
+        .label
+        .clone()
+        .unwrap_or_else(|| format!("${:04X}", routine.address))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_program;
+
+    fn analyze(lines: &[&str]) -> StackAnalysis {
+        analyze_stack(lines, &parse_program(lines).unwrap())
+    }
+
+    fn kinds(analysis: &StackAnalysis) -> Vec<(usize, WarningKind)> {
+        analysis
+            .warnings
+            .iter()
+            .map(|warning| (warning.line_num, warning.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_depth() {
+        let analysis = analyze(&[
+            "main: push.w ax",
+            "  call draw",
+            "  pop.w ax",
+            "  call ax",
+            "  halt",
+            "draw: push.b al",
+            "  call clear",
+            "  pop.b al",
+            "  ret",
+            "clear: push.w bx",
+            "  push.w cx",
+            "  pop.w cx",
+            "  pop.w bx",
+            "  ret",
+        ]);
+        assert!(analysis.warnings.is_empty());
+        let labels: Vec<Option<&str>> = analysis
+            .routines
+            .iter()
+            .map(|routine| routine.label.as_deref())
+            .collect();
+        assert_eq!(labels, vec![Some("main"), Some("draw"), Some("clear")]);
+        let summary: Vec<(Vec<usize>, bool, usize, Option<usize>)> = analysis
+            .routines
+            .iter()
+            .map(|routine| {
+                (
+                    routine.calls.clone(),
+                    routine.has_unknown_calls,
+                    routine.local_depth,
+                    routine.max_depth,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (vec![1], true, 2, Some(11)),
+                (vec![2], false, 1, Some(7)),
+                (vec![], false, 4, Some(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recursion() {
+        let analysis = analyze(&[
+            "main: call a",
+            "  halt",
+            "a: push.b al",
+            "  call b",
+            "  pop.b al",
+            "  ret",
+            "b: call a",
+            "  ret",
+        ]);
+        assert_eq!(kinds(&analysis), vec![(6, WarningKind::Recursion)]);
+        assert_eq!(analysis.warnings[0].message, "Recursive call: a -> b -> a");
+        assert_eq!(analysis.warnings[0].span, 8..9);
+        assert!(analysis
+            .routines
+            .iter()
+            .all(|routine| routine.max_depth.is_none()));
+    }
+
+    #[test]
+    fn test_imbalance() {
+        let analysis = analyze(&[
+            "main: call a",
+            "  call b",
+            "  halt",
+            "a: push.w ax",
+            "  ret",
+            "b: pop.b al",
+            "  ret",
+            "c: push.b al",
+            "  jmp c",
+        ]);
+        assert_eq!(
+            kinds(&analysis),
+            vec![
+                (4, WarningKind::StackImbalance),
+                (6, WarningKind::StackImbalance),
+            ]
+        );
+        assert_eq!(
+            analysis.warnings[0].message,
+            "Returns with 2 bytes still pushed"
+        );
+        assert_eq!(
+            analysis.warnings[1].message,
+            "Returns after popping 1 bytes more than pushed"
+        );
+
+        let analysis = analyze(&[
+            "main: push.b al",
+            ".loop: push.b al",
+            "  jne .loop",
+            "  halt",
+        ]);
+        assert_eq!(kinds(&analysis), vec![(1, WarningKind::StackImbalance)]);
+        assert_eq!(
+            analysis.warnings[0].message,
+            "Stack has 1 or 2 bytes pushed here depending on the path"
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut lines = vec!["main: call a", "  halt", "a:"];
+        lines.extend(["  push.w ax"; 500]);
+        lines.extend(["  pop.w ax"; 500]);
+        lines.push("  ret");
+        let lines: Vec<&str> = lines.into_iter().collect();
+        let analysis = analyze_stack(&lines, &parse_program(&lines).unwrap());
+        assert_eq!(analysis.routines[0].max_depth, Some(1002));
+        assert_eq!(kinds(&analysis), vec![(0, WarningKind::StackOverflow)]);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::ParseError::*;
+use crate::{ParseError, StringReason};
+use std::collections::HashMap;
+
+/// Maps characters to the byte values used by the game's font
+pub type Charmap = HashMap<char, u8>;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StrChar {
+    Char(char),
+    /// From \xNN, used as is
+    Byte(u8),
+}
+
+/// Converts a double quoted string literal into chars, processing escape sequences
+///
+/// Supported escapes are \n, \r, \t, \0, \\, \', \" and \xNN
+pub fn decode_string(line_num: usize, literal: &str) -> Result<Vec<StrChar>, ParseError> {
+    let invalid = |reason: StringReason| InvalidString(line_num, literal.to_string(), reason);
+    let content = literal
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .ok_or_else(|| invalid(StringReason::Unquoted))?;
+    let mut output = vec![];
+    let mut chars = content.chars();
+    while let Some(chr) = chars.next() {
+        let decoded = match chr {
+            '\\' => match chars.next() {
+                Some('n') => StrChar::Char('\n'),
+                Some('r') => StrChar::Char('\r'),
+                Some('t') => StrChar::Char('\t'),
+                Some('0') => StrChar::Byte(0),
+                Some('\\') => StrChar::Char('\\'),
+                Some('\'') => StrChar::Char('\''),
+                Some('"') => StrChar::Char('"'),
+                Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    if hex.len() != 2 {
+                        return Err(invalid(StringReason::HexEscape));
+                    }
+                    let byte = u8::from_str_radix(&hex, 16)
+                        .map_err(|_| invalid(StringReason::HexEscape))?;
+                    StrChar::Byte(byte)
+                }
+                Some(other) => return Err(invalid(StringReason::UnknownEscape(other))),
+                None => return Err(invalid(StringReason::TrailingBackslash)),
+            },
+            '"' => return Err(invalid(StringReason::UnescapedQuote)),
+            _ => StrChar::Char(chr),
+        };
+        output.push(decoded);
+    }
+    Ok(output)
+}
+
+/// Returns the char from a single char literal, e.g. 'A'
+pub fn parse_char_literal(literal: &str) -> Option<char> {
+    let content = literal.strip_prefix('\'')?.strip_suffix('\'')?;
+    let mut chars = content.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(chr), None, None) => Some(chr),
+        (Some('\\'), Some('\''), None) => Some('\''),
+        _ => None,
+    }
+}
+
+/// Converts a char to a byte using the charmap, or ASCII if there is no charmap
+pub fn encode_char(
+    line_num: usize,
+    literal: &str,
+    chr: char,
+    charmap: Option<&Charmap>,
+) -> Result<u8, ParseError> {
+    match charmap {
+        Some(charmap) => charmap
+            .get(&chr)
+            .copied()
+            .ok_or_else(|| UnmappedCharacter(line_num, literal.to_string(), chr)),
+        None => {
+            if chr.is_ascii() {
+                Ok(chr as u8)
+            } else {
+                Err(InvalidStringCharacter(line_num, literal.to_string(), chr))
+            }
+        }
+    }
+}
+
+/// Converts chars to bytes using the charmap, or ASCII if there is no charmap
+pub fn encode_string(
+    line_num: usize,
+    literal: &str,
+    chars: &[StrChar],
+    charmap: Option<&Charmap>,
+) -> Result<Vec<u8>, ParseError> {
+    chars
+        .iter()
+        .map(|chr| match chr {
+            StrChar::Byte(byte) => Ok(*byte),
+            StrChar::Char(chr) => encode_char(line_num, literal, *chr, charmap),
+        })
+        .collect()
+}
+
+pub fn parse_string(
+    line_num: usize,
+    literal: &str,
+    charmap: Option<&Charmap>,
+) -> Result<Vec<u8>, ParseError> {
+    let chars = decode_string(line_num, literal)?;
+    encode_string(line_num, literal, &chars, charmap)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(parse_string(0, r#""""#, None).unwrap(), Vec::<u8>::new());
+        assert_eq!(parse_string(0, r#""Hi""#, None).unwrap(), vec![72, 105]);
+        assert_eq!(
+            parse_string(0, r#""a, b""#, None).unwrap(),
+            vec![97, 44, 32, 98]
+        );
+        assert_eq!(
+            parse_string(0, r#""\n\r\t\0\\\'\"""#, None).unwrap(),
+            vec![10, 13, 9, 0, 92, 39, 34]
+        );
+        assert_eq!(
+            parse_string(0, r#""\x00\xFF\x4a""#, None).unwrap(),
+            vec![0, 255, 74]
+        );
+
+        assert!(parse_string(0, r#""a"#, None).is_err());
+        assert!(parse_string(0, r#"a""#, None).is_err());
+        assert!(parse_string(0, r#""a"b""#, None).is_err());
+        assert!(parse_string(0, r#""\q""#, None).is_err());
+        assert!(parse_string(0, r#""\x1""#, None).is_err());
+        assert!(parse_string(0, r#""\xGG""#, None).is_err());
+        assert!(parse_string(0, r#""\""#, None).is_err());
+        assert!(matches!(
+            parse_string(0, r#""café""#, None),
+            Err(InvalidStringCharacter(0, _, 'é'))
+        ));
+    }
+
+    #[test]
+    fn test_string_reasons() {
+        let reason = |literal: &str| match decode_string(0, literal) {
+            Err(InvalidString(_, _, reason)) => Some(reason),
+            _ => None,
+        };
+        assert_eq!(reason("\"abc"), Some(StringReason::Unquoted));
+        assert_eq!(reason("\"\\x1\""), Some(StringReason::HexEscape));
+        assert_eq!(reason("\"\\xZZ\""), Some(StringReason::HexEscape));
+        assert_eq!(reason("\"\\q\""), Some(StringReason::UnknownEscape('q')));
+        assert_eq!(reason("\"a\\\""), Some(StringReason::TrailingBackslash));
+        assert_eq!(reason("\"a\"b\""), Some(StringReason::UnescapedQuote));
+        assert_eq!(reason("\"ok\""), None);
+    }
+
+    #[test]
+    fn test_charmap() {
+        let charmap = Charmap::from([('A', 1), ('B', 2), ('é', 3), ('\n', 4)]);
+        assert_eq!(
+            parse_string(0, r#""AB\né\x00""#, Some(&charmap)).unwrap(),
+            vec![1, 2, 4, 3, 0]
+        );
+        assert!(matches!(
+            parse_string(0, r#""ABC""#, Some(&charmap)),
+            Err(UnmappedCharacter(0, _, 'C'))
+        ));
+    }
+
+    #[test]
+    fn test_char_literals() {
+        assert_eq!(parse_char_literal("'A'"), Some('A'));
+        assert_eq!(parse_char_literal("' '"), Some(' '));
+        assert_eq!(parse_char_literal("'''"), Some('\''));
+        assert_eq!(parse_char_literal(r"'\''"), Some('\''));
+        assert_eq!(parse_char_literal("'é'"), Some('é'));
+
+        assert_eq!(parse_char_literal("''"), None);
+        assert_eq!(parse_char_literal("'AB'"), None);
+        assert_eq!(parse_char_literal("A"), None);
+        assert_eq!(parse_char_literal("'A"), None);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::control_blocks::ControlCode;
+use crate::strings::Charmap;
+use crate::ParseError::{DuplicateSymbol, InvalidLabel};
+use crate::{LabelReason, ParseError};
+use maikor_platform::registers::id;
+use std::collections::HashMap;
+
+pub const ANON_LABEL: &str = "@@";
+pub const ANON_FORWARD: &str = "+";
+pub const ANON_BACKWARD: &str = "-";
+/// Labels made by control directives (e.g. `__loop0`) start with this, so other names can't
+pub const HIDDEN_PREFIX: &str = "__";
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Symbols {
+    constants: HashMap<String, u16>,
+    charmap: Option<Charmap>,
+    labels: HashMap<String, u16>,
+    /// Line number and name of global labels, used to find the scope of local labels
+    scopes: Vec<(usize, String)>,
+    /// Line number, name (`@@`, `+` or `-`) and address of anonymous labels
+    anonymous: Vec<(usize, String, u16)>,
+    /// Code generated for control directives (`.if_eq`, `.endl`, etc), by line number
+    control_code: HashMap<usize, ControlCode>,
+}
+
+impl Symbols {
+    pub fn with_charmap(charmap: Option<Charmap>) -> Self {
+        Self {
+            charmap,
+            ..Self::default()
+        }
+    }
+
+    /// Adds (or replaces) a mapping, the charmap is created if necessary
+    pub fn map_char(&mut self, chr: char, value: u8) {
+        self.charmap
+            .get_or_insert_with(Charmap::new)
+            .insert(chr, value);
+    }
+
+    pub fn charmap(&self) -> Option<&Charmap> {
+        self.charmap.as_ref()
+    }
+
+    pub fn add_constant(
+        &mut self,
+        line_num: usize,
+        name: &str,
+        value: u16,
+    ) -> Result<(), ParseError> {
+        if self.constants.contains_key(name) || self.labels.contains_key(name) {
+            return Err(DuplicateSymbol(line_num, name.to_string()));
+        }
+        self.constants.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    pub fn constant(&self, name: &str) -> Option<u16> {
+        self.constants.get(name).copied()
+    }
+
+    /// Adds a label (without the trailing :), the address is set to 0 until `set_label_address` is called
+    ///
+    /// Local labels (starting with .) are scoped to the previous global label
+    ///
+    /// Returns the full name of the label
+    pub fn add_label(&mut self, line_num: usize, label: &str) -> Result<String, ParseError> {
+        if label == ANON_LABEL || label == ANON_FORWARD || label == ANON_BACKWARD {
+            self.anonymous.push((line_num, label.to_string(), 0));
+            return Ok(label.to_string());
+        }
+        let invalid = |reason: LabelReason| InvalidLabel(line_num, label.to_string(), reason);
+        let (name, is_local) = match label.strip_prefix('.') {
+            Some(name) => (name, true),
+            None => (label, false),
+        };
+        if !is_valid_symbol_name(name) || name.contains('.') {
+            return Err(invalid(LabelReason::InvalidName));
+        }
+        if id::from_name(name).is_ok() {
+            return Err(invalid(LabelReason::Register));
+        }
+        if !is_local && name.starts_with(HIDDEN_PREFIX) {
+            return Err(invalid(LabelReason::Reserved));
+        }
+        let full_name = if is_local {
+            match self.scopes.last() {
+                Some((_, scope)) => format!("{scope}{label}"),
+                None => return Err(invalid(LabelReason::NoScope)),
+            }
+        } else {
+            self.scopes.push((line_num, label.to_string()));
+            label.to_string()
+        };
+        if self.constants.contains_key(&full_name) || self.labels.contains_key(&full_name) {
+            return Err(DuplicateSymbol(line_num, full_name));
+        }
+        self.labels.insert(full_name.clone(), 0);
+        Ok(full_name)
+    }
+
+    /// `name` must be the full name returned from `add_label`
+    pub fn set_label_address(&mut self, line_num: usize, name: &str, address: u16) {
+        if let Some(label) = self
+            .anonymous
+            .iter_mut()
+            .find(|(num, _, _)| *num == line_num)
+        {
+            label.2 = address;
+        } else if let Some(value) = self.labels.get_mut(name) {
+            *value = address;
+        }
+    }
+
+    /// Adds a generated label, unlike `add_label` this doesn't change the scope of local labels
+    pub fn add_hidden_label(&mut self, line_num: usize, name: &str) -> Result<(), ParseError> {
+        if self.constants.contains_key(name) || self.labels.contains_key(name) {
+            return Err(DuplicateSymbol(line_num, name.to_string()));
+        }
+        self.labels.insert(name.to_string(), 0);
+        Ok(())
+    }
+
+    /// `name` must be a label from `add_hidden_label`
+    pub fn set_hidden_label_address(&mut self, name: &str, address: u16) {
+        if let Some(value) = self.labels.get_mut(name) {
+            *value = address;
+        }
+    }
+
+    pub fn add_control_code(&mut self, line_num: usize, code: ControlCode) {
+        self.control_code.insert(line_num, code);
+    }
+
+    pub fn control_code(&self, line_num: usize) -> Option<&ControlCode> {
+        self.control_code.get(&line_num)
+    }
+
+    pub fn labels(&self) -> &HashMap<String, u16> {
+        &self.labels
+    }
+
+    /// Returns the address for a label reference used on `line_num`
+    ///
+    /// References can be global (`main`), local (`.loop` or `main.loop`) or anonymous:
+    /// - `@f`/`@b` for the next/previous `@@`
+    /// - `+`, `++`, etc for the next, second next, etc `+`
+    /// - `-`, `--`, etc for the previous, second previous, etc `-`
+    pub fn label(&self, line_num: usize, reference: &str) -> Option<u16> {
+        let address = |label: &(usize, String, u16)| label.2;
+        let is_repeated = |chr: char| !reference.is_empty() && reference.chars().all(|c| c == chr);
+        if reference.eq_ignore_ascii_case("@f") {
+            self.anonymous
+                .iter()
+                .find(|(num, name, _)| name == ANON_LABEL && *num > line_num)
+                .map(address)
+        } else if reference.eq_ignore_ascii_case("@b") {
+            self.anonymous
+                .iter()
+                .rev()
+                .find(|(num, name, _)| name == ANON_LABEL && *num <= line_num)
+                .map(address)
+        } else if is_repeated('+') {
+            self.anonymous
+                .iter()
+                .filter(|(num, name, _)| name == ANON_FORWARD && *num > line_num)
+                .nth(reference.len() - 1)
+                .map(address)
+        } else if is_repeated('-') {
+            self.anonymous
+                .iter()
+                .rev()
+                .filter(|(num, name, _)| name == ANON_BACKWARD && *num <= line_num)
+                .nth(reference.len() - 1)
+                .map(address)
+        } else if reference.starts_with('.') {
+            let (_, scope) = self.scopes.iter().rev().find(|(num, _)| *num <= line_num)?;
+            self.labels.get(&format!("{scope}{reference}")).copied()
+        } else {
+            self.labels.get(reference).copied()
+        }
+    }
+}
+
+/// Returns true if `text` can only be a local or anonymous label
+pub fn is_label_reference(text: &str) -> bool {
+    text.starts_with('.')
+        || text.starts_with('@')
+        || (!text.is_empty() && (text.chars().all(|c| c == '+') || text.chars().all(|c| c == '-')))
+}
+
+/// Symbols must start with a letter or _ and can only contain letters, numbers, _ and .
+pub fn is_valid_symbol_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(chr) if chr.is_ascii_alphabetic() || chr == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_symbol_names() {
+        assert!(is_valid_symbol_name("Enemy"));
+        assert!(is_valid_symbol_name("_enemy"));
+        assert!(is_valid_symbol_name("Enemy.hp"));
+        assert!(is_valid_symbol_name("a1"));
+
+        assert!(!is_valid_symbol_name(""));
+        assert!(!is_valid_symbol_name("1a"));
+        assert!(!is_valid_symbol_name(".hp"));
+        assert!(!is_valid_symbol_name("hp-1"));
+        assert!(!is_valid_symbol_name("$hp"));
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut symbols = Symbols::default();
+        assert_eq!(symbols.add_label(0, "main").unwrap(), "main");
+        assert_eq!(symbols.add_label(1, ".loop").unwrap(), "main.loop");
+        assert_eq!(symbols.add_label(2, "@@").unwrap(), "@@");
+        assert_eq!(symbols.add_label(3, "-").unwrap(), "-");
+        assert_eq!(symbols.add_label(4, "other").unwrap(), "other");
+        assert_eq!(symbols.add_label(5, ".loop").unwrap(), "other.loop");
+        assert_eq!(symbols.add_label(6, "+").unwrap(), "+");
+        assert_eq!(symbols.add_label(7, "@@").unwrap(), "@@");
+        assert_eq!(symbols.add_label(8, "+").unwrap(), "+");
+        for (line_num, name) in [
+            (0, "main"),
+            (1, "main.loop"),
+            (2, "@@"),
+            (3, "-"),
+            (4, "other"),
+            (5, "other.loop"),
+            (6, "+"),
+            (7, "@@"),
+            (8, "+"),
+        ] {
+            symbols.set_label_address(line_num, name, line_num as u16 * 10);
+        }
+
+        assert_eq!(symbols.label(1, "main"), Some(0));
+        assert_eq!(symbols.label(1, ".loop"), Some(10));
+        assert_eq!(symbols.label(4, ".loop"), Some(50));
+        assert_eq!(symbols.label(9, "main.loop"), Some(10));
+        assert_eq!(symbols.label(0, "@f"), Some(20));
+        assert_eq!(symbols.label(2, "@F"), Some(70));
+        assert_eq!(symbols.label(2, "@b"), Some(20));
+        assert_eq!(symbols.label(6, "@b"), Some(20));
+        assert_eq!(symbols.label(3, "-"), Some(30));
+        assert_eq!(symbols.label(3, "+"), Some(60));
+        assert_eq!(symbols.label(3, "++"), Some(80));
+
+        assert_eq!(symbols.label(0, ".missing"), None);
+        assert_eq!(symbols.label(0, "@b"), None);
+        assert_eq!(symbols.label(8, "@f"), None);
+        assert_eq!(symbols.label(3, "--"), None);
+        assert_eq!(symbols.label(3, "+++"), None);
+        assert_eq!(symbols.label(3, "missing"), None);
+    }
+
+    #[test]
+    fn test_invalid_labels() {
+        let mut symbols = Symbols::default();
+        assert!(symbols.add_label(0, ".loop").is_err());
+        assert!(symbols.add_label(0, "1main").is_err());
+        assert!(symbols.add_label(0, "main.loop").is_err());
+        assert!(symbols.add_label(0, "ax").is_err());
+        assert!(symbols.add_label(0, "@").is_err());
+        symbols.add_label(0, "main").unwrap();
+        assert!(symbols.add_label(1, "main").is_err());
+        assert!(symbols.add_label(1, ".a.b").is_err());
+        symbols.add_constant(2, "A", 1).unwrap();
+        assert!(symbols.add_label(2, "A").is_err());
+        assert!(symbols.add_constant(2, "main", 1).is_err());
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut symbols = Symbols::default();
+        symbols.add_constant(0, "A", 1).unwrap();
+        assert!(symbols.add_constant(1, "A", 2).is_err());
+        assert_eq!(symbols.constant("A"), Some(1));
+        assert_eq!(symbols.constant("B"), None);
+    }
+
+    #[test]
+    fn test_label_reasons() {
+        let mut symbols = Symbols::default();
+        let reason = |result: Result<String, ParseError>| match result {
+            Err(InvalidLabel(_, _, reason)) => Some(reason),
+            _ => None,
+        };
+        assert_eq!(
+            reason(symbols.add_label(0, ".loop")),
+            Some(LabelReason::NoScope)
+        );
+        assert_eq!(
+            reason(symbols.add_label(0, "1main")),
+            Some(LabelReason::InvalidName)
+        );
+        assert_eq!(
+            reason(symbols.add_label(0, "ax")),
+            Some(LabelReason::Register)
+        );
+        assert_eq!(
+            reason(symbols.add_label(0, "__loop0")),
+            Some(LabelReason::Reserved)
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use crate::arg_matching::Argument;
+use crate::interpreter::{arg_spans, split_comment};
+use crate::ops::expects_bytes;
+use crate::ParsedLine;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// Comments starting with this (after the #) configure warnings for the line, e.g. `# lint: allow(unused_label)`
+const PRAGMA: &str = "lint:";
+/// Ops reported by `DuplicateLine`, see `is_repeat_safe`
+const REPEAT_SAFE_OPS: [&str; 14] = [
+    "CPY.B", "CPY.W", "AND.B", "AND.W", "OR.B", "OR.W", "CMP.B", "CMP.W", "CMPS.B", "CMPS.W",
+    "CLR.B", "CLR.W", "TST.B", "TST.W",
+];
+
+/// Type of warning, each can be disabled with `LintOptions` or a `# lint: allow(name)` comment
+///
+/// `NegativeNumber`, `ImplicitSize` and `DuplicateLine` are found by the parser and are in
+/// `Program.warnings`, the others are found by `lint`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum WarningKind {
+    /// A word op writing a number that fits in a byte to memory, e.g. `CPY.W $100, 1`
+    WordOpByteValue,
+    /// A label that is never used, except the label at address 0
+    UnusedLabel,
+    /// An op writing to `FLG`, e.g. `CPY.B FLG, 0` or `SWAP.B AL, FLG` (`POP.B FLG` is allowed)
+    FlagsWrite,
+    /// A jump or call to an address in the middle of an instruction
+    JumpMidInstruction,
+    /// Code that can't be reached from the entry point or a label, e.g. after `JMP` or `HALT`
+    UnreachableCode,
+    /// A negative number literal, which is stored as an unsigned number, e.g. `-1` as `65535`
+    NegativeNumber,
+    /// A number in an op without a size, which is stored as a byte to match the op,
+    /// e.g. `BMUL $1, 2`
+    ImplicitSize,
+    /// A line that is the same as the previous line, for ops where repeating has no effect,
+    /// e.g. `CPY.B AL, 1`
+    DuplicateLine,
+    /// A routine that calls itself, directly or through other routines
+    Recursion,
+    /// A `RET` with a different number of bytes pushed and popped, or code reached with
+    /// different numbers of bytes pushed
+    StackImbalance,
+    /// The worst case stack depth is more than the stack size
+    StackOverflow,
+}
+
+impl WarningKind {
+    pub const ALL: [WarningKind; 11] = [
+        WarningKind::WordOpByteValue,
+        WarningKind::UnusedLabel,
+        WarningKind::FlagsWrite,
+        WarningKind::JumpMidInstruction,
+        WarningKind::UnreachableCode,
+        WarningKind::NegativeNumber,
+        WarningKind::ImplicitSize,
+        WarningKind::DuplicateLine,
+        WarningKind::Recursion,
+        WarningKind::StackImbalance,
+        WarningKind::StackOverflow,
+    ];
+
+    /// Name used in `# lint: allow(name)` comments
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::WordOpByteValue => "word_op_byte_value",
+            WarningKind::UnusedLabel => "unused_label",
+            WarningKind::FlagsWrite => "flags_write",
+            WarningKind::JumpMidInstruction => "jump_mid_instruction",
+            WarningKind::UnreachableCode => "unreachable_code",
+            WarningKind::NegativeNumber => "negative_number",
+            WarningKind::ImplicitSize => "implicit_size",
+            WarningKind::DuplicateLine => "duplicate_line",
+            WarningKind::Recursion => "recursion",
+            WarningKind::StackImbalance => "stack_imbalance",
+            WarningKind::StackOverflow => "stack_overflow",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<WarningKind> {
+        WarningKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+    }
+}
+
+impl Display for WarningKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Something that assembles but is probably wrong
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Warning {
+    pub line_num: usize,
+    /// Byte range in the source line
+    pub span: Range<usize>,
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} on line {} [{}]",
+            self.message, self.line_num, self.kind
+        )
+    }
+}
+
+pub fn warning(line_num: usize, span: Range<usize>, kind: WarningKind, message: String) -> Warning {
+    Warning {
+        line_num,
+        span,
+        kind,
+        message,
+    }
+}
+
+/// Range of the code in a line, excluding whitespace and comments
+pub fn code_span(lines: &[&str], line_num: usize) -> Range<usize> {
+    let line = lines.get(line_num).copied().unwrap_or_default();
+    let start = line.len() - line.trim_start().len();
+    start..start + split_comment(line).0.len()
+}
+
+/// Range of an arg, or the whole code if the arg isn't found
+pub fn arg_span(lines: &[&str], line_num: usize, idx: usize) -> Range<usize> {
+    let line = lines.get(line_num).copied().unwrap_or_default();
+    arg_spans(line)
+        .get(idx)
+        .cloned()
+        .unwrap_or_else(|| code_span(lines, line_num))
+}
+
+/// Returns the allowed warnings for each line
+///
+/// Warnings are allowed with `# lint: allow(name, ...)` (or `allow(all)`) either on the
+/// line itself or on the comment lines directly before it
+pub fn allowed_warnings(lines: &[&str]) -> Vec<HashSet<WarningKind>> {
+    let mut allowed = vec![HashSet::new(); lines.len()];
+    let mut pending = HashSet::new();
+    for (line_num, line) in lines.iter().enumerate() {
+        let (code, comment) = split_comment(line);
+        if let Some(kinds) = comment.and_then(parse_pragma) {
+            pending.extend(kinds);
+        }
+        if !code.is_empty() {
+            allowed[line_num] = std::mem::take(&mut pending);
+        }
+    }
+    allowed
+}
+
+/// Unknown warning names are ignored
+fn parse_pragma(comment: &str) -> Option<Vec<WarningKind>> {
+    let names = comment
+        .trim_start_matches('#')
+        .trim()
+        .strip_prefix(PRAGMA)?
+        .trim()
+        .strip_prefix("allow(")?
+        .strip_suffix(')')?;
+    let mut kinds = vec![];
+    for name in names.split(',').map(str::trim) {
+        if name == "all" {
+            kinds.extend(WarningKind::ALL);
+        } else if let Some(kind) = WarningKind::from_name(name) {
+            kinds.push(kind);
+        }
+    }
+    Some(kinds)
+}
+
+/// Finds warnings for a line while parsing, `previous` is the line before `line` (if any)
+pub fn check_line(
+    lines: &[&str],
+    line: &ParsedLine,
+    previous: Option<&ParsedLine>,
+) -> Vec<Warning> {
+    let mut warnings = vec![];
+    let line_num = line.line.num;
+    let (op, args) = match &line.line.command {
+        Some(command) => command,
+        None => return warnings,
+    };
+    for (idx, arg) in args.iter().enumerate() {
+        let is_negative = arg
+            .strip_prefix('-')
+            .map(|num| num.starts_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or_default();
+        if let (true, Ok(num)) = (is_negative, arg.parse::<i16>()) {
+            warnings.push(warning(
+                line_num,
+                arg_span(lines, line_num, idx),
+                WarningKind::NegativeNumber,
+                format!("{arg} is stored as {}", num as u16),
+            ));
+        }
+    }
+    if op.starts_with('.') {
+        return warnings;
+    }
+    let op = op.to_ascii_uppercase();
+    //numbers are words unless the op expects bytes, so bytes here were picked to match the op
+    if !expects_bytes(&op) && !op.ends_with(".W") {
+        for (idx, arg) in line.arguments.iter().enumerate() {
+            if let Argument::Byte(_) = arg {
+                warnings.push(warning(
+                    line_num,
+                    arg_span(lines, line_num, idx),
+                    WarningKind::ImplicitSize,
+                    format!("{} is stored as a byte as {op} has no size", args[idx]),
+                ));
+            }
+        }
+    }
+    if let Some((prev_op, prev_args)) = previous.and_then(|prev| prev.line.command.as_ref()) {
+        if line.line.label.is_none()
+            && is_repeat_safe(&op, &line.arguments)
+            && prev_op.eq_ignore_ascii_case(&op)
+            && prev_args == args
+        {
+            warnings.push(warning(
+                line_num,
+                code_span(lines, line_num),
+                WarningKind::DuplicateLine,
+                format!(
+                    "Same as line {}",
+                    previous.map(|prev| prev.line.num).unwrap_or_default()
+                ),
+            ));
+        }
+    }
+    warnings
+}
+
+/// Ops that have no effect when repeated, as long as the args are registers or numbers
+/// (memory may be IO and `(bx)+`, etc change the register)
+fn is_repeat_safe(op: &str, arguments: &[Argument]) -> bool {
+    REPEAT_SAFE_OPS.contains(&op)
+        && arguments.iter().all(|arg| {
+            arg.is_plain_register() || matches!(arg, Argument::Byte(_) | Argument::Word(_))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_program;
+
+    #[test]
+    fn test_pragmas() {
+        let lines = [
+            "nop # lint: allow(duplicate_line)",
+            "# lint: allow(unused_label, missing)",
+            "# lint:allow(negative_number)",
+            "",
+            "nop",
+            "nop # lint: allow(all)",
+            "nop # allow(all)",
+        ];
+        let allowed = allowed_warnings(&lines);
+        assert_eq!(allowed[0], HashSet::from([WarningKind::DuplicateLine]));
+        assert_eq!(
+            allowed[4],
+            HashSet::from([WarningKind::UnusedLabel, WarningKind::NegativeNumber])
+        );
+        assert_eq!(allowed[5], HashSet::from(WarningKind::ALL));
+        assert!(allowed[6].is_empty());
+    }
+
+    #[test]
+    fn test_implicit_size() {
+        let lines = [
+            "bmul $1, 2",
+            "bmul al, 3",
+            "jrf 2",
+            "cpy.b al, 2",
+            "bmul al, bl",
+        ];
+        let program = parse_program(&lines).unwrap();
+        let warnings: Vec<(usize, Range<usize>, WarningKind)> = program
+            .warnings
+            .iter()
+            .map(|warning| (warning.line_num, warning.span.clone(), warning.kind))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                (0, 9..10, WarningKind::ImplicitSize),
+                (1, 9..10, WarningKind::ImplicitSize)
+            ]
+        );
+        assert_eq!(
+            program.warnings[0].message,
+            "2 is stored as a byte as BMUL has no size"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_names() {
+        for kind in WarningKind::ALL {
+            let json = serde_json::to_string(&kind).unwrap();
+            assert_eq!(json, format!("\"{}\"", kind.name()));
+            assert_eq!(serde_json::from_str::<WarningKind>(&json).unwrap(), kind);
+        }
+    }
+}
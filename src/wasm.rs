@@ -0,0 +1,184 @@
+//! JavaScript bindings, all results are plain objects (see each function for the shape)
+//!
+//! Diagnostics are `{ line, span: { start, end }, severity, code, message }` where `line` is
+//! zero based, `span` is in UTF-16 columns (as JS strings use), `severity` is `"error"` or
+//! `"warning"` and `code` is the warning name or error code (e.g. `E0013`)
+
+use crate::warnings::code_span;
+use crate::{
+    disassemble as disassemble_bytes, lint_program, parse_line_from_str, parse_program,
+    LintOptions, ParseError, ParsedLine, Program, Warning,
+};
+use serde_json::{json, Value};
+use std::ops::Range;
+use wasm_bindgen::prelude::*;
+
+/// Returns `{ bytes, lines: [{ line, address, bytes }], labels, diagnostics }`, `bytes`, `lines`
+/// and `labels` are empty if there's an error
+#[wasm_bindgen(js_name = parseProgram)]
+pub fn parse_program_js(source: &str) -> JsValue {
+    to_js(program_json(source))
+}
+
+/// Returns `{ bytes, diagnostics }` for a single line without labels or constants
+#[wasm_bindgen(js_name = parseLine)]
+pub fn parse_line_js(text: &str) -> JsValue {
+    to_js(line_json(text))
+}
+
+/// Returns `[{ address, bytes, text }]`
+#[wasm_bindgen(js_name = disassemble)]
+pub fn disassemble_js(bytes: &[u8]) -> JsValue {
+    to_js(disassembly_json(bytes))
+}
+
+/// Returns the parse error or lint warnings as diagnostics
+#[wasm_bindgen(js_name = diagnostics)]
+pub fn diagnostics_js(source: &str) -> JsValue {
+    to_js(program_json(source)["diagnostics"].clone())
+}
+
+fn to_js(value: Value) -> JsValue {
+    js_sys::JSON::parse(&value.to_string()).unwrap_or(JsValue::NULL)
+}
+
+fn program_json(source: &str) -> Value {
+    let lines: Vec<&str> = source.lines().collect();
+    match parse_program(&lines) {
+        Ok(program) => {
+            let diagnostics: Vec<Value> = lint_program(&lines, &program, &LintOptions::default())
+                .iter()
+                .map(|warning| warning_json(&lines, warning))
+                .collect();
+            json!({
+                "bytes": program.bytes,
+                "lines": program.lines.iter().map(parsed_line_json).collect::<Vec<Value>>(),
+                "labels": labels_json(&program),
+                "diagnostics": diagnostics,
+            })
+        }
+        Err(err) => json!({
+            "bytes": [],
+            "lines": [],
+            "labels": {},
+            "diagnostics": [error_json(&lines, &err)],
+        }),
+    }
+}
+
+fn line_json(text: &str) -> Value {
+    match parse_line_from_str(text) {
+        Ok(line) => json!({ "bytes": line.bytes, "diagnostics": [] }),
+        Err(err) => json!({ "bytes": [], "diagnostics": [error_json(&[text], &err)] }),
+    }
+}
+
+fn disassembly_json(bytes: &[u8]) -> Value {
+    disassemble_bytes(bytes)
+        .iter()
+        .map(|line| {
+            json!({
+                "address": line.address,
+                "bytes": line.bytes,
+                "text": line.text,
+            })
+        })
+        .collect()
+}
+
+fn parsed_line_json(line: &ParsedLine) -> Value {
+    json!({
+        "line": line.line.num,
+        "address": line.address,
+        "bytes": line.bytes,
+    })
+}
+
+fn labels_json(program: &Program) -> Value {
+    program
+        .labels
+        .iter()
+        .map(|(name, address)| (name.clone(), json!(address)))
+        .collect::<serde_json::Map<String, Value>>()
+        .into()
+}
+
+fn warning_json(lines: &[&str], warning: &Warning) -> Value {
+    json!({
+        "line": warning.line_num,
+        "span": span_json(lines, warning.line_num, &warning.span),
+        "severity": "warning",
+        "code": warning.kind.name(),
+        "message": warning.message,
+    })
+}
+
+/// The span covers the contents of the line the error is on (excluding comments)
+fn error_json(lines: &[&str], err: &ParseError) -> Value {
+    let line_num = err.line_num().unwrap_or_default();
+    json!({
+        "line": line_num,
+        "span": span_json(lines, line_num, &code_span(lines, line_num)),
+        "severity": "error",
+        "code": err.code(),
+        "message": err.to_string(),
+    })
+}
+
+/// Converts a byte range to UTF-16 columns
+fn span_json(lines: &[&str], line_num: usize, span: &Range<usize>) -> Value {
+    let line = lines.get(line_num).copied().unwrap_or_default();
+    let column = |idx: usize| line[..idx.min(line.len())].encode_utf16().count();
+    json!({ "start": column(span.start), "end": column(span.end) })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_program_json() {
+        let result = program_json("main: nop\n  jmp main\n  nop # unreachable");
+        assert_eq!(result["bytes"], json!([0, 176, 0, 0, 0]));
+        assert_eq!(
+            result["lines"][1],
+            json!({ "line": 1, "address": 1, "bytes": [176, 0, 0] })
+        );
+        assert_eq!(result["labels"], json!({ "main": 0 }));
+        assert_eq!(
+            result["diagnostics"],
+            json!([{
+                "line": 2,
+                "span": { "start": 2, "end": 5 },
+                "severity": "warning",
+                "code": "unreachable_code",
+                "message": "Unreachable code after JMP on line 1",
+            }])
+        );
+    }
+
+    #[test]
+    fn test_error_json() {
+        let result = program_json("nop\n  add.b ax, 1 # comment");
+        assert_eq!(result["bytes"], json!([]));
+        let diagnostic = &result["diagnostics"][0];
+        assert_eq!(diagnostic["line"], 1);
+        assert_eq!(diagnostic["span"], json!({ "start": 2, "end": 13 }));
+        assert_eq!(diagnostic["severity"], "error");
+        assert_eq!(diagnostic["code"], "E0013");
+
+        assert_eq!(line_json("nop")["bytes"], json!([0]));
+        assert_eq!(line_json("nop al")["diagnostics"][0]["line"], 0);
+    }
+
+    #[test]
+    fn test_disassembly_json() {
+        assert_eq!(
+            disassembly_json(&[0, 176, 0, 0]),
+            json!([
+                { "address": 0, "bytes": [0], "text": "NOP" },
+                { "address": 1, "bytes": [176, 0, 0], "text": "JMP $x0000" },
+            ])
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Compiles `tests/ffi/*.c` with the system C compiler (`CC` or `cc`) against the static
+//! library and runs them
+//!
+//! The static library is built with `cargo rustc --crate-type staticlib` in its own target dir
+#![cfg(feature = "ffi")]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Builds the static library and returns its path
+fn build_static_lib() -> PathBuf {
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+    let status = Command::new(env!("CARGO"))
+        .args([
+            "rustc",
+            "--lib",
+            "--features",
+            "ffi",
+            "--crate-type",
+            "staticlib",
+        ])
+        .arg("--manifest-path")
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to build static library");
+    target_dir.join("debug/libmaikor_asm_parser.a")
+}
+
+fn compile_and_run(name: &str) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let lib = build_static_lib();
+    let compiler = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    let status = Command::new(compiler)
+        .arg(root.join("tests/ffi").join(format!("{name}.c")))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-o")
+        .arg(&output)
+        .arg(lib)
+        .args(["-lpthread", "-ldl", "-lm"])
+        .status()
+        .expect("C compiler not found, set CC");
+    assert!(status.success(), "failed to compile {name}.c");
+    let result = Command::new(&output).output().unwrap();
+    assert!(
+        result.status.success(),
+        "{name} failed: {}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+}
+
+#[test]
+fn assemble() {
+    compile_and_run("assemble");
+}
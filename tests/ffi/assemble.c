@@ -0,0 +1,62 @@
+#include <stdio.h>
+#include <string.h>
+
+#include "maikor_asm_parser.h"
+
+#define CHECK(cond)                                                    \
+  do {                                                                 \
+    if (!(cond)) {                                                     \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+              #cond);                                                  \
+      return 1;                                                        \
+    }                                                                  \
+  } while (0)
+
+static int test_assemble(void) {
+  const char *source = "main: nop\n  jmp main";
+  MaikorAsmResult *result =
+      maikor_asm_assemble((const uint8_t *)source, strlen(source));
+  CHECK(result != NULL);
+  CHECK(result->errors_len == 0);
+  CHECK(result->errors == NULL);
+  CHECK(result->bytes_len == 4);
+  const uint8_t expected[] = {0x00, 0xB0, 0x00, 0x00};
+  CHECK(memcmp(result->bytes, expected, sizeof(expected)) == 0);
+  maikor_asm_result_free(result);
+  return 0;
+}
+
+static int test_errors(void) {
+  const char *source = "nop\n  add.b ax, 1";
+  MaikorAsmResult *result =
+      maikor_asm_assemble((const uint8_t *)source, strlen(source));
+  CHECK(result != NULL);
+  CHECK(result->bytes == NULL);
+  CHECK(result->bytes_len == 0);
+  CHECK(result->errors_len == 1);
+  CHECK(result->errors[0].line == 1);
+  CHECK(result->errors[0].column == 8);
+  CHECK(strcmp(result->errors[0].code, "E0013") == 0);
+  CHECK(strstr(result->errors[0].message, "ADD.B") != NULL);
+  maikor_asm_result_free(result);
+  return 0;
+}
+
+static int test_null(void) {
+  CHECK(maikor_asm_assemble(NULL, 1) == NULL);
+  MaikorAsmResult *result = maikor_asm_assemble(NULL, 0);
+  CHECK(result != NULL);
+  CHECK(result->bytes_len == 0);
+  CHECK(result->errors_len == 0);
+  maikor_asm_result_free(result);
+  maikor_asm_result_free(NULL);
+  return 0;
+}
+
+int main(void) {
+  if (test_assemble() || test_errors() || test_null()) {
+    return 1;
+  }
+  printf("ok\n");
+  return 0;
+}
@@ -0,0 +1,56 @@
+//! Run under Node with `wasm-pack test --node --features wasm`
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use js_sys::{Array, Reflect, JSON};
+use maikor_asm_parser::wasm::{diagnostics_js, disassemble_js, parse_line_js, parse_program_js};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn get(value: &JsValue, key: &str) -> JsValue {
+    Reflect::get(value, &JsValue::from_str(key)).unwrap()
+}
+
+fn to_json(value: &JsValue) -> String {
+    JSON::stringify(value).unwrap().into()
+}
+
+#[wasm_bindgen_test]
+fn parse_program() {
+    let result = parse_program_js("main: nop\n  jmp main");
+    assert_eq!(to_json(&get(&result, "bytes")), "[0,176,0,0]");
+    assert_eq!(to_json(&get(&result, "labels")), r#"{"main":0}"#);
+    assert_eq!(Array::from(&get(&result, "lines")).length(), 2);
+    assert_eq!(Array::from(&get(&result, "diagnostics")).length(), 0);
+}
+
+#[wasm_bindgen_test]
+fn parse_line() {
+    let result = parse_line_js("add.b al, 1");
+    assert_eq!(to_json(&get(&result, "bytes")), "[34,1,1]");
+}
+
+#[wasm_bindgen_test]
+fn diagnostics() {
+    let result = diagnostics_js("nop\n  add.b ax, 1");
+    let diagnostic = Array::from(&result).get(0);
+    assert_eq!(get(&diagnostic, "line").as_f64(), Some(1.0));
+    assert_eq!(
+        get(&diagnostic, "severity").as_string().as_deref(),
+        Some("error")
+    );
+    assert_eq!(
+        to_json(&get(&diagnostic, "span")),
+        r#"{"end":13,"start":2}"#
+    );
+}
+
+#[wasm_bindgen_test]
+fn disassemble() {
+    let result = disassemble_js(&[0, 176, 0, 0]);
+    let line = Array::from(&result).get(1);
+    assert_eq!(
+        get(&line, "text").as_string().as_deref(),
+        Some("JMP $x0000")
+    );
+    assert_eq!(get(&line, "address").as_f64(), Some(1.0));
+}